@@ -32,6 +32,11 @@ struct TuiConsole {
     enrich_matches: indicatif::ProgressBar,
     enrich_mismatches: indicatif::ProgressBar,
     enrich_blocks: indicatif::ProgressBar,
+    enrich_db_size: indicatif::ProgressBar,
+    enrich_utxos: indicatif::ProgressBar,
+    enrich_fetch_latency: indicatif::ProgressBar,
+    enrich_fetch_histogram: indicatif::ProgressBar,
+    enrich_hit_ratio: indicatif::ProgressBar,
 }
 
 impl TuiConsole {
@@ -51,6 +56,22 @@ impl TuiConsole {
         )
     }
 
+    /// Spinner for values that go up and down, `value` is the indicatif
+    /// placeholder used to render the position
+    fn build_gauge_spinner(
+        name: &str,
+        value: &str,
+        container: &indicatif::MultiProgress,
+    ) -> indicatif::ProgressBar {
+        container.add(
+            indicatif::ProgressBar::new_spinner().with_style(
+                indicatif::ProgressStyle::default_spinner()
+                    .template(&format!("{{spinner}} {:<20} {{msg:<20}} {}", name, value))
+                    .unwrap(),
+            ),
+        )
+    }
+
     fn new() -> Self {
         let container = indicatif::MultiProgress::new();
 
@@ -68,6 +89,23 @@ impl TuiConsole {
             enrich_matches: Self::build_counter_spinner("enrich matches", &container),
             enrich_mismatches: Self::build_counter_spinner("enrich mismatches", &container),
             enrich_blocks: Self::build_counter_spinner("enrich blocks", &container),
+            enrich_db_size: Self::build_gauge_spinner("enrich db size", "{bytes:>8}", &container),
+            enrich_utxos: Self::build_gauge_spinner("enrich utxos", "{pos:>8}", &container),
+            enrich_fetch_latency: Self::build_gauge_spinner(
+                "enrich fetch (us)",
+                "{pos:>8}",
+                &container,
+            ),
+            enrich_fetch_histogram: Self::build_gauge_spinner(
+                "enrich fetch buckets",
+                "{prefix}",
+                &container,
+            ),
+            enrich_hit_ratio: Self::build_gauge_spinner(
+                "enrich hit ratio",
+                "{pos:>8}%",
+                &container,
+            ),
             reducer_ops_count: Self::build_counter_spinner("reducer ops", &container),
             storage_ops_count: Self::build_counter_spinner("storage ops", &container),
         }
@@ -89,6 +127,9 @@ impl TuiConsole {
 
             match tether.read_metrics() {
                 Ok(readings) => {
+                    // cumulative counts of blocks fetched under 1ms, 10ms, 100ms and 1s
+                    let mut fetch_buckets = [None; 4];
+
                     for (key, value) in readings {
                         match (tether.name(), key, value) {
                             (_, "chain_tip", Reading::Gauge(x)) => {
@@ -129,9 +170,45 @@ impl TuiConsole {
                                 self.enrich_blocks.set_position(x);
                                 self.enrich_blocks.set_message(state);
                             }
+                            (_, "enrich_db_size", Reading::Gauge(x)) => {
+                                self.enrich_db_size.set_position(x as u64);
+                                self.enrich_db_size.set_message(state);
+                            }
+                            (_, "enrich_utxos", Reading::Gauge(x)) => {
+                                self.enrich_utxos.set_position(x as u64);
+                                self.enrich_utxos.set_message(state);
+                            }
+                            (_, "enrich_fetch_latency_us", Reading::Gauge(x)) => {
+                                self.enrich_fetch_latency.set_position(x as u64);
+                                self.enrich_fetch_latency.set_message(state);
+                            }
+                            (_, "enrich_hit_ratio", Reading::Gauge(x)) => {
+                                self.enrich_hit_ratio.set_position(x as u64);
+                                self.enrich_hit_ratio.set_message(state);
+                            }
+                            (_, "enrich_fetch_le_1ms", Reading::Count(x)) => {
+                                fetch_buckets[0] = Some(x)
+                            }
+                            (_, "enrich_fetch_le_10ms", Reading::Count(x)) => {
+                                fetch_buckets[1] = Some(x)
+                            }
+                            (_, "enrich_fetch_le_100ms", Reading::Count(x)) => {
+                                fetch_buckets[2] = Some(x)
+                            }
+                            (_, "enrich_fetch_le_1s", Reading::Count(x)) => {
+                                fetch_buckets[3] = Some(x)
+                            }
                             _ => (),
                         }
                     }
+
+                    if let [Some(ms1), Some(ms10), Some(ms100), Some(s1)] = fetch_buckets {
+                        self.enrich_fetch_histogram.set_prefix(format!(
+                            "<=1ms: {} <=10ms: {} <=100ms: {} <=1s: {}",
+                            ms1, ms10, ms100, s1
+                        ));
+                        self.enrich_fetch_histogram.set_message(state);
+                    }
                 }
                 Err(err) => {
                     println!("couldn't read metrics");
@@ -110,9 +110,12 @@ pub fn run(args: &Args) -> Result<(), scrolls::Error> {
         .source
         .bootstrapper(&chain, &config.intersect, &config.finalize, &policy);
 
-    let enrich = config.enrich.unwrap_or_default().bootstrapper(&policy);
+    let enrich = config
+        .enrich
+        .unwrap_or_default()
+        .bootstrapper(&config.intersect, &policy);
 
-    let reducer = reducers::Bootstrapper::new(config.reducers, &chain, &policy);
+    let reducer = reducers::Bootstrapper::new(config.reducers, &chain, &policy)?;
 
     let storage = config.storage.plugin(&chain, &config.intersect, &policy);
 
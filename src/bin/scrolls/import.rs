@@ -0,0 +1,49 @@
+use clap;
+use scrolls::{crosscut, enrich};
+use std::convert::TryInto;
+
+pub fn run(args: &Args) -> Result<(), scrolls::Error> {
+    env_logger::init();
+
+    let point = args.point.clone().try_into()?;
+
+    let mut importer = enrich::local::Importer::open(args.engine, &args.db_path)?;
+
+    log::info!("importing utxos from {:?}", args.snapshot);
+
+    enrich::snapshot::import_file(&args.snapshot, &args.format, &mut importer)?;
+
+    let imported = importer.finish(&point)?;
+
+    log::info!(
+        "imported {} utxos, enrich db is at {}",
+        imported,
+        args.point.to_string()
+    );
+
+    Ok(())
+}
+
+#[derive(clap::Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(long, value_parser, default_value = "sled")]
+    //#[clap(description = "engine of the db used by the enrich stage, sled or redb")]
+    engine: enrich::kv::Engine,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "path of the db used by the enrich stage")]
+    db_path: String,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "file with the utxo set of the ledger")]
+    snapshot: std::path::PathBuf,
+
+    #[clap(long, value_parser, default_value = "json")]
+    //#[clap(description = "format of the snapshot file, json or cbor")]
+    format: enrich::snapshot::SnapshotFormat,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "point of the chain the snapshot was taken at, as `slot,hash`")]
+    point: crosscut::PointArg,
+}
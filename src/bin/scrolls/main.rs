@@ -3,6 +3,9 @@ use std::process;
 
 mod console;
 mod daemon;
+mod import;
+mod migrate;
+mod upgrade;
 
 #[derive(Parser)]
 #[clap(name = "Scrolls")]
@@ -10,6 +13,9 @@ mod daemon;
 #[clap(author, version, about, long_about = None)]
 enum Scrolls {
     Daemon(daemon::Args),
+    ImportUtxos(import::Args),
+    MigrateEnrich(migrate::Args),
+    UpgradeEnrich(upgrade::Args),
 }
 
 fn main() {
@@ -17,6 +23,9 @@ fn main() {
 
     let result = match args {
         Scrolls::Daemon(x) => daemon::run(&x),
+        Scrolls::ImportUtxos(x) => import::run(&x),
+        Scrolls::MigrateEnrich(x) => migrate::run(&x),
+        Scrolls::UpgradeEnrich(x) => upgrade::run(&x),
     };
 
     if let Err(err) = &result {
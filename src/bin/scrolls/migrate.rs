@@ -0,0 +1,43 @@
+use clap;
+use scrolls::enrich::kv;
+
+pub fn run(args: &Args) -> Result<(), scrolls::Error> {
+    env_logger::init();
+
+    let from = kv::Db::open(args.from_engine, &args.from_path)?;
+    let to = kv::Db::open(args.to_engine, &args.to_path)?;
+
+    log::info!(
+        "copying enrich db from {} ({:?}) to {} ({:?})",
+        args.from_path,
+        args.from_engine,
+        args.to_path,
+        args.to_engine
+    );
+
+    let copied = kv::copy_all(&from, &to)?;
+
+    log::info!("copied {} entries", copied);
+
+    Ok(())
+}
+
+#[derive(clap::Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(long, value_parser, default_value = "sled")]
+    //#[clap(description = "engine of the existing enrich db")]
+    from_engine: kv::Engine,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "path of the existing enrich db")]
+    from_path: String,
+
+    #[clap(long, value_parser, default_value = "redb")]
+    //#[clap(description = "engine of the new enrich db")]
+    to_engine: kv::Engine,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "path of the new enrich db")]
+    to_path: String,
+}
@@ -0,0 +1,26 @@
+use clap;
+use scrolls::enrich;
+
+pub fn run(args: &Args) -> Result<(), scrolls::Error> {
+    env_logger::init();
+
+    log::info!("upgrading enrich db at {}", args.db_path);
+
+    let converted = enrich::local::upgrade(args.engine, &args.db_path)?;
+
+    log::info!("converted {} utxos", converted);
+
+    Ok(())
+}
+
+#[derive(clap::Args)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(long, value_parser, default_value = "sled")]
+    //#[clap(description = "engine of the enrich db, sled or redb")]
+    engine: enrich::kv::Engine,
+
+    #[clap(long, value_parser)]
+    //#[clap(description = "path of the enrich db to upgrade")]
+    db_path: String,
+}
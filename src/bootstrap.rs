@@ -24,7 +24,9 @@ pub fn build(
     mut reducer: reducers::Bootstrapper,
     mut storage: storage::Bootstrapper,
 ) -> Result<Pipeline, crate::Error> {
-    let cursor = storage.build_cursor();
+    let mut cursor = storage.build_cursor();
+
+    enrich.reconcile(cursor.last_point()?)?;
 
     let mut pipeline = Pipeline::new();
 
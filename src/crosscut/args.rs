@@ -110,6 +110,13 @@ impl Default for MagicArg {
     }
 }
 
+/// Defines where to start syncing the chain from
+///
+/// The `Epoch`, `Timestamp` and `TipMinus` variants don't require prior
+/// knowledge of block hashes. They are resolved by skimming chain-sync headers
+/// from the closest well-known point of the network, which might take a while
+/// on long chains. `TipMinus` skims all the way to the tip, so it's only
+/// available through N2N.
 #[derive(Debug, Deserialize, Clone)]
 #[serde(tag = "type", content = "value")]
 pub enum IntersectConfig {
@@ -117,6 +124,12 @@ pub enum IntersectConfig {
     Origin,
     Point(u64, String),
     Fallbacks(Vec<(u64, String)>),
+    /// start from the first block of the given epoch
+    Epoch(u64),
+    /// start from the first block on or after the given unix timestamp
+    Timestamp(u64),
+    /// start from X blocks behind the tip of the chain
+    TipMinus(u64),
 }
 
 impl IntersectConfig {
@@ -146,23 +159,58 @@ impl IntersectConfig {
             _ => None,
         }
     }
+
+    /// Returns the absolute slot that the processing should start from, for
+    /// those variants that are defined in terms of time
+    pub fn get_target_slot(&self, chain: &ChainWellKnownInfo) -> Option<u64> {
+        match self {
+            IntersectConfig::Epoch(epoch) => Some(super::epochs::epoch_first_slot(chain, *epoch)),
+            IntersectConfig::Timestamp(timestamp) => {
+                Some(super::time::wallclock_to_slot(chain, *timestamp))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Action to take when the most recent point of the storage cursor is no
+/// longer part of the chain but an older one is
+#[derive(Debug, Deserialize, Clone)]
+pub enum CursorRecovery {
+    /// roll back storage to the most recent cursor point still on chain
+    Rollback,
+    /// refuse to continue, requiring manual intervention
+    Fail,
+}
+
+impl Default for CursorRecovery {
+    fn default() -> Self {
+        CursorRecovery::Fail
+    }
 }
 
 /// Optional configuration to stop processing new blocks after processing:
 ///   1. a block with the given hash
 ///   2. the first block on or after a given absolute slot
-///   3. TODO: a total of X blocks
+///   3. a total of X blocks
+///   4. the first block on or after the start of a given epoch
+///   5. the first block on or after a given unix timestamp (block time)
+///   6. the last block available when reaching the tip of the chain
 #[derive(Deserialize, Debug, Clone)]
 pub struct FinalizeConfig {
     until_hash: Option<String>,
     max_block_slot: Option<u64>,
-    // max_block_quantity: Option<u64>,
+    max_block_quantity: Option<u64>,
+    max_block_epoch: Option<u64>,
+    max_block_timestamp: Option<u64>,
+    until_tip: Option<bool>,
 }
 
 pub fn should_finalize(
     config: &Option<FinalizeConfig>,
+    chain: &ChainWellKnownInfo,
     last_point: &Point,
-    // block_count: u64,
+    block_count: u64,
 ) -> bool {
     let config = match config {
         Some(x) => x,
@@ -171,7 +219,9 @@ pub fn should_finalize(
 
     if let Some(expected) = &config.until_hash {
         if let Point::Specific(_, current) = last_point {
-            return expected == &hex::encode(current);
+            if expected == &hex::encode(current) {
+                return true;
+            }
         }
     }
 
@@ -181,15 +231,37 @@ pub fn should_finalize(
         }
     }
 
-    // if let Some(max) = config.max_block_quantity {
-    //     if block_count >= max {
-    //         return true;
-    //     }
-    // }
+    if let Some(max) = config.max_block_quantity {
+        if block_count >= max {
+            return true;
+        }
+    }
+
+    if let Some(max) = config.max_block_epoch {
+        if super::epochs::slot_epoch(chain, last_point.slot_or_default()) >= max {
+            return true;
+        }
+    }
+
+    if let Some(max) = config.max_block_timestamp {
+        if super::time::slot_to_wallclock(chain, last_point.slot_or_default()) >= max {
+            return true;
+        }
+    }
 
     false
 }
 
+/// Evaluates if we should finalize once chain-sync reports that we reached
+/// the tip of the chain. Blocks that haven't reached the required min depth by
+/// then are not processed.
+pub fn should_finalize_at_tip(config: &Option<FinalizeConfig>) -> bool {
+    match config {
+        Some(x) => x.until_tip.unwrap_or(false),
+        None => false,
+    }
+}
+
 /// Well-known information about the blockhain network
 ///
 /// Some of the logic in Scrolls depends on particular characteristic of the
@@ -298,6 +370,26 @@ impl ChainWellKnownInfo {
         }
     }
 
+    /// Well-known points that can be used as anchors to start skimming the
+    /// chain from, ordered from most recent to oldest
+    pub fn known_points(&self) -> Vec<Point> {
+        let mut points = vec![];
+
+        if let Ok(hash) = hex::decode(&self.shelley_known_hash) {
+            if !hash.is_empty() {
+                points.push(Point::Specific(self.shelley_known_slot, hash));
+            }
+        }
+
+        if let Ok(hash) = hex::decode(&self.byron_known_hash) {
+            if !hash.is_empty() {
+                points.push(Point::Specific(self.byron_known_slot, hash));
+            }
+        }
+
+        points
+    }
+
     /// Uses the value of the magic to return either mainnet or testnet
     /// hardcoded values.
     pub fn try_from_magic(magic: u64) -> Result<ChainWellKnownInfo, Error> {
@@ -3,15 +3,18 @@
 
 use pallas::ledger::traverse::MultiEraBlock;
 
-fn post_byron_epoch_for_slot(shelley_known_slot: u64, shelley_epoch_length: u32, slot: u64) -> u64 {
-    let last_byron_epoch_no = 208;
-
+fn post_byron_epoch_for_slot(
+    first_shelley_epoch_no: u64,
+    shelley_known_slot: u64,
+    shelley_epoch_length: u32,
+    slot: u64,
+) -> u64 {
     let shelley_known_slot = shelley_known_slot as u64;
     let shelley_epoch_length = shelley_epoch_length as u64;
 
     let shelley_epoch_no = (slot - shelley_known_slot) / shelley_epoch_length;
 
-    return last_byron_epoch_no + shelley_epoch_no;
+    return first_shelley_epoch_no + shelley_epoch_no;
 }
 
 fn byron_epoch_for_slot(byron_epoch_length: u32, byron_slot_length: u32, slot: u64) -> u64 {
@@ -21,13 +24,89 @@ fn byron_epoch_for_slot(byron_epoch_length: u32, byron_slot_length: u32, slot: u
     return slot / (byron_epoch_length / byron_slot_length);
 }
 
+/// Number of the first Shelley epoch, assuming that the Shelley known slot of
+/// the chain is the first slot of the era (true for mainnet, testnet and
+/// pre-prod)
+fn first_shelley_epoch(chain: &super::ChainWellKnownInfo) -> u64 {
+    byron_epoch_for_slot(
+        chain.byron_epoch_length,
+        chain.byron_slot_length,
+        chain.shelley_known_slot,
+    )
+}
+
+fn shelley_epoch_for_slot(chain: &super::ChainWellKnownInfo, slot: u64) -> u64 {
+    post_byron_epoch_for_slot(
+        first_shelley_epoch(chain),
+        chain.shelley_known_slot,
+        chain.shelley_epoch_length,
+        slot,
+    )
+}
+
 pub fn block_epoch(chain: &super::ChainWellKnownInfo, block: &MultiEraBlock) -> u64 {
-    let slot = block.slot();
+    slot_epoch(chain, block.slot())
+}
+
+pub fn slot_epoch(chain: &super::ChainWellKnownInfo, slot: u64) -> u64 {
+    if slot < chain.shelley_known_slot {
+        byron_epoch_for_slot(chain.byron_epoch_length, chain.byron_slot_length, slot)
+    } else {
+        shelley_epoch_for_slot(chain, slot)
+    }
+}
+
+/// Computes the absolute slot where the given epoch starts, the inverse of
+/// `slot_epoch` using the same era boundary assumptions
+pub fn epoch_first_slot(chain: &super::ChainWellKnownInfo, epoch: u64) -> u64 {
+    let first_shelley_epoch_no = first_shelley_epoch(chain);
+
+    if epoch < first_shelley_epoch_no {
+        let byron_epoch_length = chain.byron_epoch_length as u64;
+        let byron_slot_length = chain.byron_slot_length as u64;
+
+        epoch * (byron_epoch_length / byron_slot_length)
+    } else {
+        let shelley_epoch_length = chain.shelley_epoch_length as u64;
 
-    match block.era() {
-        pallas::ledger::traverse::Era::Byron => {
-            byron_epoch_for_slot(chain.byron_epoch_length, chain.byron_slot_length, slot)
+        chain.shelley_known_slot + (epoch - first_shelley_epoch_no) * shelley_epoch_length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crosscut::ChainWellKnownInfo;
+
+    #[test]
+    fn epoch_slot_roundtrip() {
+        let chain = ChainWellKnownInfo::mainnet();
+
+        for epoch in [0, 1, 207, 208, 209, 390] {
+            let slot = epoch_first_slot(&chain, epoch);
+            assert_eq!(slot_epoch(&chain, slot), epoch);
+            assert_eq!(
+                slot_epoch(&chain, slot.saturating_sub(1)),
+                epoch.saturating_sub(1)
+            );
         }
-        _ => post_byron_epoch_for_slot(chain.shelley_known_slot, chain.shelley_epoch_length, slot),
+
+        assert_eq!(epoch_first_slot(&chain, 208), 4492800);
+    }
+
+    #[test]
+    fn era_boundary_follows_the_chain() {
+        // the legacy testnet switched to Shelley at epoch 74
+        let chain = ChainWellKnownInfo::testnet();
+
+        assert_eq!(slot_epoch(&chain, 1598399), 73);
+        assert_eq!(slot_epoch(&chain, 1598400), 74);
+        assert_eq!(epoch_first_slot(&chain, 74), 1598400);
+
+        // and pre-prod at epoch 4
+        let chain = ChainWellKnownInfo::preprod();
+
+        assert_eq!(slot_epoch(&chain, 86400), 4);
+        assert_eq!(epoch_first_slot(&chain, 5), 86400 + 432000);
     }
 }
@@ -1,6 +1,6 @@
 use pallas::ledger::{
     addresses::Address,
-    traverse::{MultiEraBlock, MultiEraTx},
+    traverse::{Asset, MultiEraBlock, MultiEraOutput, MultiEraTx},
 };
 use serde::Deserialize;
 
@@ -82,6 +82,41 @@ impl AddressPattern {
     }
 }
 
+/// Selects tx outputs by their address or by the policies of the assets they
+/// hold. An output matches if it satisfies any of the patterns.
+#[derive(Deserialize, Clone, Default)]
+pub struct OutputPattern {
+    pub addresses: Option<Vec<AddressPattern>>,
+
+    /// hex-encoded policy ids
+    pub policies: Option<Vec<String>>,
+}
+
+impl OutputPattern {
+    pub fn matches(&self, output: &MultiEraOutput) -> bool {
+        if let Some(patterns) = &self.addresses {
+            if let Ok(addr) = output.address() {
+                if patterns.iter().any(|p| p.matches(addr.clone())) {
+                    return true;
+                }
+            }
+        }
+
+        if let Some(policies) = &self.policies {
+            let holds_policy = output.non_ada_assets().iter().any(|asset| match asset {
+                Asset::NativeAsset(policy, _, _) => policies.contains(&hex::encode(policy)),
+                _ => false,
+            });
+
+            if holds_policy {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct BlockPattern {
     pub slot_before: Option<u64>,
@@ -0,0 +1,91 @@
+//! Conversion of transaction metadata into JSON
+
+use pallas::ledger::primitives::alonzo::Metadatum;
+use serde_json::{json, Map, Value};
+
+/// Text to use as JSON key for a metadatum, maps can be keyed by anything
+fn key_to_string(key: &Metadatum) -> String {
+    match key {
+        Metadatum::Text(x) => x.clone(),
+        Metadatum::Int(x) => {
+            let value: i128 = (*x).into();
+            value.to_string()
+        }
+        Metadatum::Bytes(x) => hex::encode(x.as_slice()),
+        other => metadatum_to_json(other).to_string(),
+    }
+}
+
+/// Converts a metadatum into JSON, similar to the "no schema" conversion of
+/// cardano-cli: maps become objects with text keys and bytes are hex encoded
+pub fn metadatum_to_json(value: &Metadatum) -> Value {
+    match value {
+        Metadatum::Int(x) => {
+            let value: i128 = (*x).into();
+
+            match i64::try_from(value) {
+                Ok(x) => json!(x),
+                Err(_) => json!(value.to_string()),
+            }
+        }
+        Metadatum::Bytes(x) => json!(hex::encode(x.as_slice())),
+        Metadatum::Text(x) => json!(x),
+        Metadatum::Array(x) => Value::Array(x.iter().map(metadatum_to_json).collect()),
+        Metadatum::Map(x) => {
+            let object: Map<_, _> = x
+                .iter()
+                .map(|(k, v)| (key_to_string(k), metadatum_to_json(v)))
+                .collect();
+
+            Value::Object(object)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::codec::utils::KeyValuePairs;
+
+    use super::*;
+
+    fn text(value: &str) -> Metadatum {
+        Metadatum::Text(value.to_string())
+    }
+
+    #[test]
+    fn converts_scalars() {
+        assert_eq!(
+            metadatum_to_json(&Metadatum::Int((-5i64).into())),
+            json!(-5)
+        );
+        assert_eq!(metadatum_to_json(&text("hi")), json!("hi"));
+
+        let bytes = Metadatum::Bytes(vec![0xca, 0xfe].into());
+        assert_eq!(metadatum_to_json(&bytes), json!("cafe"));
+
+        // integers beyond the range of JSON numbers become strings
+        let big = Metadatum::Int(u64::MAX.into());
+        assert_eq!(metadatum_to_json(&big), json!(u64::MAX.to_string()));
+    }
+
+    #[test]
+    fn converts_collections() {
+        let array = Metadatum::Array(vec![text("a"), Metadatum::Int(1u64.into())].into());
+        assert_eq!(metadatum_to_json(&array), json!(["a", 1]));
+
+        let nested = Metadatum::Array(vec![text("k")].into());
+
+        let map = Metadatum::Map(KeyValuePairs::Def(vec![
+            (text("name"), text("x")),
+            (Metadatum::Int(7u64.into()), text("int")),
+            (Metadatum::Bytes(vec![0x01].into()), text("bytes")),
+            (nested, text("array")),
+        ]));
+
+        // keys that aren't text are rendered as text
+        assert_eq!(
+            metadatum_to_json(&map),
+            json!({ "name": "x", "7": "int", "01": "bytes", "[\"k\"]": "array" })
+        );
+    }
+}
@@ -1,6 +1,9 @@
 mod args;
 pub mod epochs;
 pub mod filters;
+pub mod metadata;
+pub mod plutus;
 pub mod policies;
+pub mod time;
 
 pub use args::*;
@@ -0,0 +1,403 @@
+//! Conversion of Plutus data into JSON, either generically or following the
+//! schemas of a CIP-57 Plutus blueprint.
+
+use std::collections::{HashMap, HashSet};
+
+use pallas::ledger::primitives::alonzo::{BigInt, Constr, PlutusData};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Constructor index of a Plutus constr, decoding the compact CBOR tags
+pub fn constr_index(data: &Constr<PlutusData>) -> Option<u64> {
+    match data.tag {
+        121..=127 => Some(data.tag - 121),
+        1280..=1400 => Some(data.tag - 1280 + 7),
+        102 => data.any_constructor,
+        _ => None,
+    }
+}
+
+fn int_to_json(value: i128) -> Value {
+    // JSON numbers can't hold the whole range of Plutus integers
+    match i64::try_from(value) {
+        Ok(x) => json!(x),
+        Err(_) => json!(value.to_string()),
+    }
+}
+
+fn bignum_to_u128(bytes: &[u8]) -> Option<u128> {
+    match bytes.len() {
+        0..=16 => Some(bytes.iter().fold(0u128, |acc, b| (acc << 8) | *b as u128)),
+        _ => None,
+    }
+}
+
+/// Bignums are rendered as decimal strings, or as hex when they don't fit in
+/// 128 bits
+fn bigint_to_json(value: &BigInt) -> Value {
+    match value {
+        BigInt::Int(x) => {
+            let value: i128 = (*x).into();
+            int_to_json(value)
+        }
+        BigInt::BigUInt(x) => match bignum_to_u128(x.as_slice()) {
+            Some(n) => json!(n.to_string()),
+            None => json!(format!("0x{}", hex::encode(x.as_slice()))),
+        },
+        // negative bignums encode the value as -1 - n
+        BigInt::BigNInt(x) => match bignum_to_u128(x.as_slice()).map(i128::try_from) {
+            Some(Ok(n)) => json!((-1 - n).to_string()),
+            _ => json!(format!("-1-0x{}", hex::encode(x.as_slice()))),
+        },
+    }
+}
+
+/// Schema-less representation of Plutus data: constrs as objects with the
+/// constructor index and fields, bytes in hex and maps as lists of pairs
+pub fn data_to_json(data: &PlutusData) -> Value {
+    match data {
+        PlutusData::Constr(x) => json!({
+            "constructor": constr_index(x),
+            "fields": x.fields.iter().map(data_to_json).collect::<Vec<_>>(),
+        }),
+        PlutusData::Map(x) => Value::Array(
+            x.iter()
+                .map(|(k, v)| json!([data_to_json(k), data_to_json(v)]))
+                .collect(),
+        ),
+        PlutusData::BigInt(x) => bigint_to_json(x),
+        PlutusData::BoundedBytes(x) => json!(hex::encode(x.as_slice())),
+        PlutusData::Array(x) => Value::Array(x.iter().map(data_to_json).collect()),
+    }
+}
+
+#[derive(Deserialize)]
+struct BlueprintDatum {
+    schema: Value,
+}
+
+#[derive(Deserialize)]
+struct BlueprintValidator {
+    title: String,
+    datum: Option<BlueprintDatum>,
+}
+
+#[derive(Deserialize)]
+struct BlueprintFile {
+    validators: Vec<BlueprintValidator>,
+    #[serde(default)]
+    definitions: HashMap<String, Value>,
+}
+
+/// Decodes the datums of a validator following the schema declared in a
+/// CIP-57 blueprint (eg: the `plutus.json` generated by Aiken)
+pub struct DatumSchema {
+    root: Value,
+    definitions: HashMap<String, Value>,
+}
+
+/// The datum is valid CBOR, it just doesn't follow the schema
+fn mismatch(schema: &Value, data: &PlutusData) -> crate::Error {
+    crate::Error::message(format!(
+        "datum doesn't match schema {}: {}",
+        schema,
+        data_to_json(data)
+    ))
+}
+
+impl DatumSchema {
+    pub fn new(root: Value, definitions: HashMap<String, Value>) -> Self {
+        Self { root, definitions }
+    }
+
+    /// Loads the datum schema of the validator with the given title
+    pub fn from_blueprint(path: &str, validator: &str) -> Result<Self, crate::Error> {
+        let file = std::fs::File::open(path).map_err(|x| crate::Error::config(x.to_string()))?;
+
+        let blueprint: BlueprintFile = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|x| crate::Error::config(x.to_string()))?;
+
+        let schema = blueprint
+            .validators
+            .into_iter()
+            .find(|x| x.title == validator)
+            .ok_or_else(|| {
+                crate::Error::config(format!("validator {} not found in {}", validator, path))
+            })?
+            .datum
+            .ok_or_else(|| crate::Error::config(format!("validator {} has no datum", validator)))?
+            .schema;
+
+        let schema = Self::new(schema, blueprint.definitions);
+
+        // broken references are noticed at startup instead of at every datum
+        schema.check_references(&schema.root)?;
+
+        for definition in schema.definitions.values() {
+            schema.check_references(definition)?;
+        }
+
+        Ok(schema)
+    }
+
+    fn check_references(&self, schema: &Value) -> Result<(), crate::Error> {
+        match schema {
+            Value::Object(x) => {
+                if x.contains_key("$ref") {
+                    self.resolve(schema)?;
+                }
+
+                x.values().try_for_each(|x| self.check_references(x))
+            }
+            Value::Array(x) => x.iter().try_for_each(|x| self.check_references(x)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn decode(&self, data: &PlutusData) -> Result<Value, crate::Error> {
+        self.decode_with(&self.root, data)
+    }
+
+    /// Follows the chain of references of the schema, which might loop back
+    /// on itself in a malformed blueprint
+    fn resolve<'a>(&'a self, schema: &'a Value) -> Result<&'a Value, crate::Error> {
+        let mut schema = schema;
+        let mut visited = HashSet::new();
+
+        while let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if !visited.insert(reference) {
+                return Err(crate::Error::config(format!(
+                    "cyclic schema reference {}",
+                    reference
+                )));
+            }
+
+            // json pointers escape `/` in definition names as `~1`
+            let name = reference
+                .trim_start_matches("#/definitions/")
+                .replace("~1", "/")
+                .replace("~0", "~");
+
+            schema = self.definitions.get(&name).ok_or_else(|| {
+                crate::Error::config(format!("undefined schema reference {}", reference))
+            })?;
+        }
+
+        Ok(schema)
+    }
+
+    fn decode_constr(
+        &self,
+        schema: &Value,
+        data: &PlutusData,
+        constr: &Constr<PlutusData>,
+    ) -> Result<Value, crate::Error> {
+        let fields = schema
+            .get("fields")
+            .and_then(Value::as_array)
+            .ok_or_else(|| mismatch(schema, data))?;
+
+        if fields.len() != constr.fields.len() {
+            return Err(mismatch(schema, data));
+        }
+
+        let titled = fields.iter().all(|f| f.get("title").is_some());
+
+        let decoded = fields
+            .iter()
+            .zip(constr.fields.iter())
+            .map(|(field, value)| self.decode_with(field, value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fields = match titled {
+            true => {
+                let object: Map<_, _> = fields
+                    .iter()
+                    .filter_map(|f| f.get("title").and_then(Value::as_str))
+                    .map(String::from)
+                    .zip(decoded)
+                    .collect();
+
+                Value::Object(object)
+            }
+            false => Value::Array(decoded),
+        };
+
+        let name = match schema.get("title") {
+            Some(title) => title.clone(),
+            None => json!(constr_index(constr)),
+        };
+
+        Ok(json!({ "constructor": name, "fields": fields }))
+    }
+
+    fn decode_with(&self, schema: &Value, data: &PlutusData) -> Result<Value, crate::Error> {
+        let schema = self.resolve(schema)?;
+
+        if let Some(variants) = schema.get("anyOf").and_then(Value::as_array) {
+            let constr = match data {
+                PlutusData::Constr(x) => x,
+                _ => return Err(mismatch(schema, data)),
+            };
+
+            let index = constr_index(constr);
+
+            let variant = variants
+                .iter()
+                .map(|v| self.resolve(v))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .find(|v| v.get("index").and_then(Value::as_u64) == index)
+                .ok_or_else(|| mismatch(schema, data))?;
+
+            return self.decode_constr(variant, data, constr);
+        }
+
+        let data_type = schema.get("dataType").and_then(Value::as_str);
+
+        match (data_type, data) {
+            // opaque data, no schema to follow
+            (None, _) => Ok(data_to_json(data)),
+            (Some("integer" | "#integer"), PlutusData::BigInt(x)) => Ok(bigint_to_json(x)),
+            (Some("bytes" | "#bytes"), PlutusData::BoundedBytes(x)) => {
+                Ok(json!(hex::encode(x.as_slice())))
+            }
+            (Some("#string"), PlutusData::BoundedBytes(x)) => {
+                Ok(json!(String::from_utf8_lossy(x.as_slice())))
+            }
+            (Some("#unit"), PlutusData::Constr(_)) => Ok(Value::Null),
+            (Some("#boolean"), PlutusData::Constr(x)) => match constr_index(x) {
+                Some(0) => Ok(json!(false)),
+                Some(1) => Ok(json!(true)),
+                _ => Err(mismatch(schema, data)),
+            },
+            (Some("list" | "#list"), PlutusData::Array(items)) => {
+                let item_schema = schema.get("items").cloned().unwrap_or(Value::Null);
+
+                // tuples declare one schema per item
+                if let Value::Array(tuple) = &item_schema {
+                    if tuple.len() != items.len() {
+                        return Err(mismatch(schema, data));
+                    }
+
+                    return tuple
+                        .iter()
+                        .zip(items.iter())
+                        .map(|(s, d)| self.decode_with(s, d))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(Value::Array);
+                }
+
+                items
+                    .iter()
+                    .map(|d| self.decode_with(&item_schema, d))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Array)
+            }
+            (Some("#pair"), PlutusData::Array(items)) if items.len() == 2 => {
+                let left = schema.get("left").cloned().unwrap_or(Value::Null);
+                let right = schema.get("right").cloned().unwrap_or(Value::Null);
+
+                Ok(json!([
+                    self.decode_with(&left, &items[0])?,
+                    self.decode_with(&right, &items[1])?
+                ]))
+            }
+            (Some("map"), PlutusData::Map(pairs)) => {
+                let keys = schema.get("keys").cloned().unwrap_or(Value::Null);
+                let values = schema.get("values").cloned().unwrap_or(Value::Null);
+
+                pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        Ok(json!([
+                            self.decode_with(&keys, k)?,
+                            self.decode_with(&values, v)?
+                        ]))
+                    })
+                    .collect::<Result<Vec<_>, crate::Error>>()
+                    .map(Value::Array)
+            }
+            (Some("constructor"), PlutusData::Constr(x)) => {
+                let expected = schema.get("index").and_then(Value::as_u64);
+
+                if expected.is_some() && expected != constr_index(x) {
+                    return Err(mismatch(schema, data));
+                }
+
+                self.decode_constr(schema, data, x)
+            }
+            _ => Err(mismatch(schema, data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constr(index: u64, fields: Vec<PlutusData>) -> PlutusData {
+        PlutusData::Constr(Constr {
+            tag: 121 + index,
+            any_constructor: None,
+            fields: fields.into(),
+        })
+    }
+
+    fn bytes(value: &[u8]) -> PlutusData {
+        PlutusData::BoundedBytes(value.to_vec().into())
+    }
+
+    #[test]
+    fn decodes_following_blueprint_schema() {
+        let definitions = serde_json::from_value(json!({
+            "ByteArray": { "dataType": "bytes" },
+            "Bool": { "anyOf": [
+                { "title": "False", "dataType": "constructor", "index": 0, "fields": [] },
+                { "title": "True", "dataType": "constructor", "index": 1, "fields": [] }
+            ]},
+            "my/Datum": { "anyOf": [{
+                "title": "Datum",
+                "dataType": "constructor",
+                "index": 0,
+                "fields": [
+                    { "title": "owner", "$ref": "#/definitions/ByteArray" },
+                    { "title": "locked", "$ref": "#/definitions/Bool" }
+                ]
+            }]}
+        }))
+        .unwrap();
+
+        let schema = DatumSchema::new(json!({ "$ref": "#/definitions/my~1Datum" }), definitions);
+
+        let datum = constr(0, vec![bytes(&[0xca, 0xfe]), constr(1, vec![])]);
+
+        assert_eq!(
+            schema.decode(&datum).unwrap(),
+            json!({
+                "constructor": "Datum",
+                "fields": {
+                    "owner": "cafe",
+                    "locked": { "constructor": "True", "fields": {} }
+                }
+            })
+        );
+
+        let wrong = constr(1, vec![bytes(&[0xca, 0xfe])]);
+        assert!(schema.decode(&wrong).is_err());
+    }
+
+    #[test]
+    fn rejects_cyclic_references() {
+        let definitions = serde_json::from_value(json!({
+            "A": { "$ref": "#/definitions/B" },
+            "B": { "$ref": "#/definitions/A" }
+        }))
+        .unwrap();
+
+        let schema = DatumSchema::new(json!({ "$ref": "#/definitions/A" }), definitions);
+
+        assert!(schema.decode(&bytes(&[0xca, 0xfe])).is_err());
+        assert!(schema.check_references(&schema.root).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+// Conversions between slots and unix time assume a fixed slot length per era
+// (byron, then shelley onwards), which holds for every network configured in
+// `ChainWellKnownInfo`
+
+use super::ChainWellKnownInfo;
+
+#[inline]
+fn compute_linear_timestamp(
+    known_slot: u64,
+    known_time: u64,
+    slot_length: u32,
+    query_slot: u64,
+) -> u64 {
+    known_time + (query_slot - known_slot) * slot_length as u64
+}
+
+/// Computes the unix timestamp (in seconds) of the given absolute slot
+pub fn slot_to_wallclock(chain: &ChainWellKnownInfo, slot: u64) -> u64 {
+    if slot < chain.shelley_known_slot {
+        compute_linear_timestamp(
+            chain.byron_known_slot,
+            chain.byron_known_time,
+            chain.byron_slot_length,
+            slot,
+        )
+    } else {
+        compute_linear_timestamp(
+            chain.shelley_known_slot,
+            chain.shelley_known_time,
+            chain.shelley_slot_length,
+            slot,
+        )
+    }
+}
+
+/// Computes the first absolute slot on or after the given unix timestamp (in
+/// seconds)
+pub fn wallclock_to_slot(chain: &ChainWellKnownInfo, timestamp: u64) -> u64 {
+    let (known_slot, known_time, slot_length) = if timestamp < chain.shelley_known_time {
+        (
+            chain.byron_known_slot,
+            chain.byron_known_time,
+            chain.byron_slot_length as u64,
+        )
+    } else {
+        (
+            chain.shelley_known_slot,
+            chain.shelley_known_time,
+            chain.shelley_slot_length as u64,
+        )
+    };
+
+    let elapsed = timestamp.saturating_sub(known_time);
+
+    known_slot + (elapsed + slot_length - 1) / slot_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mainnet_known_slots() {
+        let chain = ChainWellKnownInfo::mainnet();
+
+        assert_eq!(slot_to_wallclock(&chain, 0), 1506203091);
+        assert_eq!(slot_to_wallclock(&chain, 4492799), 1596059071);
+        assert_eq!(slot_to_wallclock(&chain, 4492800), 1596059091);
+        assert_eq!(slot_to_wallclock(&chain, 85041019), 1676607310);
+    }
+
+    #[test]
+    fn mainnet_known_timestamps() {
+        let chain = ChainWellKnownInfo::mainnet();
+
+        assert_eq!(wallclock_to_slot(&chain, 1506203091), 0);
+        assert_eq!(wallclock_to_slot(&chain, 1506203092), 1);
+        assert_eq!(wallclock_to_slot(&chain, 1596059071), 4492799);
+        assert_eq!(wallclock_to_slot(&chain, 1596059091), 4492800);
+        assert_eq!(wallclock_to_slot(&chain, 1676607310), 85041019);
+    }
+}
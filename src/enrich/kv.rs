@@ -0,0 +1,426 @@
+//! Minimal key-value interface over the embedded databases that can back the
+//! local enrich stage. Keys and values are opaque bytes, ordered
+//! lexicographically.
+
+use std::{path::Path, sync::Arc};
+
+use redb::{Durability, ReadableTable, TableDefinition};
+use serde::Deserialize;
+use sled::{transaction::ConflictableTransactionResult, Transactional};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    Sled,
+    Redb,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::Sled
+    }
+}
+
+impl std::str::FromStr for Engine {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(Engine::Sled),
+            "redb" => Ok(Engine::Redb),
+            x => Err(crate::Error::message(format!(
+                "unknown db engine {}, expecting `sled` or `redb`",
+                x
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Table {
+    Utxos,
+    Undo,
+    Meta,
+    Datums,
+    /// Keys of the outputs left out by the filter, without value
+    Filtered,
+}
+
+impl Table {
+    pub const ALL: [Table; 5] = [
+        Table::Utxos,
+        Table::Undo,
+        Table::Meta,
+        Table::Datums,
+        Table::Filtered,
+    ];
+}
+
+/// Write operations to be applied together, atomically on every engine
+#[derive(Default)]
+pub struct Batch {
+    ops: Vec<(Table, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn insert(&mut self, table: Table, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.ops.push((table, key.into(), Some(value.into())));
+    }
+
+    pub fn remove(&mut self, table: Table, key: impl Into<Vec<u8>>) {
+        self.ops.push((table, key.into(), None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct SledDb {
+    db: sled::Db,
+    undo: sled::Tree,
+    meta: sled::Tree,
+    datums: sled::Tree,
+    filtered: sled::Tree,
+}
+
+impl SledDb {
+    fn open(path: &str) -> Result<Self, crate::Error> {
+        let db = sled::open(path).map_err(crate::Error::storage)?;
+        let undo = db.open_tree("undo").map_err(crate::Error::storage)?;
+        let meta = db.open_tree("meta").map_err(crate::Error::storage)?;
+        let datums = db.open_tree("datums").map_err(crate::Error::storage)?;
+        let filtered = db.open_tree("filtered").map_err(crate::Error::storage)?;
+
+        Ok(Self {
+            db,
+            undo,
+            meta,
+            datums,
+            filtered,
+        })
+    }
+
+    fn tree(&self, table: Table) -> &sled::Tree {
+        match table {
+            // the utxo set lives in the default tree for backwards compatibility
+            Table::Utxos => &self.db,
+            Table::Undo => &self.undo,
+            Table::Meta => &self.meta,
+            Table::Datums => &self.datums,
+            Table::Filtered => &self.filtered,
+        }
+    }
+}
+
+const REDB_UTXOS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("utxos");
+const REDB_UNDO: TableDefinition<&[u8], &[u8]> = TableDefinition::new("undo");
+const REDB_META: TableDefinition<&[u8], &[u8]> = TableDefinition::new("meta");
+const REDB_DATUMS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("datums");
+const REDB_FILTERED: TableDefinition<&[u8], &[u8]> = TableDefinition::new("filtered");
+
+fn redb_table(table: Table) -> TableDefinition<'static, &'static [u8], &'static [u8]> {
+    match table {
+        Table::Utxos => REDB_UTXOS,
+        Table::Undo => REDB_UNDO,
+        Table::Meta => REDB_META,
+        Table::Datums => REDB_DATUMS,
+        Table::Filtered => REDB_FILTERED,
+    }
+}
+
+#[derive(Clone)]
+pub struct RedbDb {
+    db: Arc<redb::Database>,
+    path: String,
+}
+
+impl RedbDb {
+    fn open(path: &str) -> Result<Self, crate::Error> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent).map_err(crate::Error::storage)?;
+        }
+
+        let db = redb::Database::create(path).map_err(crate::Error::storage)?;
+
+        // tables need to exist before they can be opened by read transactions
+        let txn = db.begin_write().map_err(crate::Error::storage)?;
+
+        for table in Table::ALL {
+            txn.open_table(redb_table(table))
+                .map_err(crate::Error::storage)?;
+        }
+
+        txn.commit().map_err(crate::Error::storage)?;
+
+        Ok(Self {
+            db: Arc::new(db),
+            path: path.to_owned(),
+        })
+    }
+
+    fn read_table(
+        &self,
+        table: Table,
+    ) -> Result<redb::ReadOnlyTable<&'static [u8], &'static [u8]>, crate::Error> {
+        self.db
+            .begin_read()
+            .map_err(crate::Error::storage)?
+            .open_table(redb_table(table))
+            .map_err(crate::Error::storage)
+    }
+
+    fn write(
+        &self,
+        durability: Durability,
+        op: impl FnOnce(&redb::WriteTransaction) -> Result<(), crate::Error>,
+    ) -> Result<(), crate::Error> {
+        let mut txn = self.db.begin_write().map_err(crate::Error::storage)?;
+        txn.set_durability(durability);
+        op(&txn)?;
+        txn.commit().map_err(crate::Error::storage)
+    }
+}
+
+#[derive(Clone)]
+pub enum Db {
+    Sled(SledDb),
+    Redb(RedbDb),
+}
+
+impl Db {
+    pub fn open(engine: Engine, path: &str) -> Result<Self, crate::Error> {
+        match engine {
+            Engine::Sled => SledDb::open(path).map(Db::Sled),
+            Engine::Redb => RedbDb::open(path).map(Db::Redb),
+        }
+    }
+
+    pub fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, crate::Error> {
+        match self {
+            Db::Sled(x) => x
+                .tree(table)
+                .get(key)
+                .map(|v| v.map(|v| v.to_vec()))
+                .map_err(crate::Error::storage),
+            Db::Redb(x) => x
+                .read_table(table)?
+                .get(key)
+                .map(|v| v.map(|v| v.value().to_vec()))
+                .map_err(crate::Error::storage),
+        }
+    }
+
+    pub fn apply(&self, batch: Batch) -> Result<(), crate::Error> {
+        match self {
+            Db::Sled(x) => {
+                let batches = Table::ALL.map(|table| {
+                    let mut tree_batch = sled::Batch::default();
+
+                    for (_, key, value) in batch.ops.iter().filter(|(t, _, _)| *t == table) {
+                        match value {
+                            Some(value) => tree_batch.insert(key.as_slice(), value.as_slice()),
+                            None => tree_batch.remove(key.as_slice()),
+                        }
+                    }
+
+                    tree_batch
+                });
+
+                // a single transaction across trees, so that the batch is
+                // never left partially applied
+                let trees = (
+                    x.tree(Table::Utxos),
+                    x.tree(Table::Undo),
+                    x.tree(Table::Meta),
+                    x.tree(Table::Datums),
+                    x.tree(Table::Filtered),
+                );
+
+                trees
+                    .transaction(
+                        |(utxos, undo, meta, datums, filtered)| -> ConflictableTransactionResult<(), ()> {
+                            let trees = [utxos, undo, meta, datums, filtered];
+
+                            for (tree, batch) in trees.iter().zip(&batches) {
+                                tree.apply_batch(batch)?;
+                            }
+
+                            Ok(())
+                        },
+                    )
+                    .map_err(|err| crate::Error::storage(format!("{:?}", err)))
+            }
+            // durability is deferred until the next flush, same as sled
+            Db::Redb(x) => x.write(Durability::Eventual, |txn| {
+                for (table, key, value) in batch.ops.iter() {
+                    let mut table = txn
+                        .open_table(redb_table(*table))
+                        .map_err(crate::Error::storage)?;
+
+                    match value {
+                        Some(value) => table.insert(key.as_slice(), value.as_slice()),
+                        None => table.remove(key.as_slice()),
+                    }
+                    .map_err(crate::Error::storage)?;
+                }
+
+                Ok(())
+            }),
+        }
+    }
+
+    /// Returns the keys greater or equal than `from`, in order
+    pub fn keys_from(&self, table: Table, from: &[u8]) -> Result<Vec<Vec<u8>>, crate::Error> {
+        match self {
+            Db::Sled(x) => x
+                .tree(table)
+                .range(from..)
+                .keys()
+                .map(|k| k.map(|k| k.to_vec()))
+                .collect::<Result<_, _>>()
+                .map_err(crate::Error::storage),
+            Db::Redb(x) => x
+                .read_table(table)?
+                .range(from..)
+                .map_err(crate::Error::storage)?
+                .map(|entry| entry.map(|(k, _)| k.value().to_vec()))
+                .collect::<Result<_, _>>()
+                .map_err(crate::Error::storage),
+        }
+    }
+
+    /// Removes and returns the entry with the smallest key
+    pub fn pop_first(&self, table: Table) -> Result<Option<(Vec<u8>, Vec<u8>)>, crate::Error> {
+        match self {
+            Db::Sled(x) => x
+                .tree(table)
+                .pop_min()
+                .map(|e| e.map(|(k, v)| (k.to_vec(), v.to_vec())))
+                .map_err(crate::Error::storage),
+            Db::Redb(x) => {
+                let mut popped = None;
+
+                x.write(Durability::Eventual, |txn| {
+                    let mut table = txn
+                        .open_table(redb_table(table))
+                        .map_err(crate::Error::storage)?;
+
+                    popped = table
+                        .pop_first()
+                        .map_err(crate::Error::storage)?
+                        .map(|(k, v)| (k.value().to_vec(), v.value().to_vec()));
+
+                    Ok(())
+                })?;
+
+                Ok(popped)
+            }
+        }
+    }
+
+    /// Visits every entry of the table in key order
+    pub fn scan(
+        &self,
+        table: Table,
+        mut visit: impl FnMut(&[u8], &[u8]) -> Result<(), crate::Error>,
+    ) -> Result<(), crate::Error> {
+        match self {
+            Db::Sled(x) => {
+                for entry in x.tree(table).iter() {
+                    let (k, v) = entry.map_err(crate::Error::storage)?;
+                    visit(&k, &v)?;
+                }
+            }
+            Db::Redb(x) => {
+                let table = x.read_table(table)?;
+
+                for entry in table.iter().map_err(crate::Error::storage)? {
+                    let (k, v) = entry.map_err(crate::Error::storage)?;
+                    visit(k.value(), v.value())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self, table: Table) -> Result<u64, crate::Error> {
+        match self {
+            Db::Sled(x) => Ok(x.tree(table).len() as u64),
+            Db::Redb(x) => x.read_table(table)?.len().map_err(crate::Error::storage),
+        }
+    }
+
+    pub fn is_empty(&self, table: Table) -> Result<bool, crate::Error> {
+        match self {
+            Db::Sled(x) => Ok(x.tree(table).is_empty()),
+            Db::Redb(x) => x
+                .read_table(table)?
+                .is_empty()
+                .map_err(crate::Error::storage),
+        }
+    }
+
+    /// Amount of bytes used by the db files
+    pub fn size_on_disk(&self) -> Result<u64, crate::Error> {
+        match self {
+            Db::Sled(x) => x.db.size_on_disk().map_err(crate::Error::storage),
+            Db::Redb(x) => std::fs::metadata(&x.path)
+                .map(|m| m.len())
+                .map_err(crate::Error::storage),
+        }
+    }
+
+    /// Makes sure every write applied so far is persisted to disk
+    pub fn flush(&self) -> Result<(), crate::Error> {
+        match self {
+            Db::Sled(x) => x.db.flush().map(|_| ()).map_err(crate::Error::storage),
+            Db::Redb(x) => x.write(Durability::Immediate, |_| Ok(())),
+        }
+    }
+}
+
+/// Amount of entries written on each batch while copying between dbs
+const COPY_BATCH_SIZE: usize = 10_000;
+
+/// Copies every entry of every table into an empty db, possibly backed by a
+/// different engine. Returns the amount of entries copied.
+pub fn copy_all(from: &Db, to: &Db) -> Result<u64, crate::Error> {
+    for table in Table::ALL {
+        if !to.is_empty(table)? {
+            return Err(crate::Error::config(format!(
+                "target db is not empty, found entries in {:?} table",
+                table
+            )));
+        }
+    }
+
+    let mut copied = 0;
+
+    for table in Table::ALL {
+        let mut batch = Batch::default();
+
+        from.scan(table, |key, value| {
+            batch.insert(table, key, value);
+            copied += 1;
+
+            if batch.len() >= COPY_BATCH_SIZE {
+                to.apply(std::mem::take(&mut batch))?;
+                log::info!("copied {} entries so far", copied);
+            }
+
+            Ok(())
+        })?;
+
+        to.apply(batch)?;
+    }
+
+    to.flush()?;
+
+    Ok(copied)
+}
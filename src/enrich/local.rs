@@ -0,0 +1,1562 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use gasket::{
+    error::AsWorkError,
+    runtime::{spawn_stage, WorkOutcome},
+};
+
+use pallas::{
+    codec::{minicbor, utils::Bytes},
+    crypto::hash::Hash,
+    ledger::traverse::{ComputeHash, Era, MultiEraBlock, MultiEraTx, OutputRef},
+    network::miniprotocols::Point,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
+
+use super::{
+    kv::{self, Table},
+    metrics::FetchMetrics,
+};
+use crate::{
+    bootstrap, crosscut,
+    model::{self, BlockContext},
+    prelude::AppliesPolicy,
+};
+
+type InputPort = gasket::messaging::TwoPhaseInputPort<model::RawBlockPayload>;
+type OutputPort = gasket::messaging::OutputPort<model::EnrichedBlockPayload>;
+
+/// Default amount of blocks to keep undo records for, matches the security
+/// parameter (k) of mainnet
+const DEFAULT_ROLLBACK_WINDOW: u64 = 2160;
+
+/// How often to refresh the size of the db on disk
+const DB_STATS_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    pub db_path: String,
+
+    /// Number of recent blocks that can be undone on rollback
+    pub rollback_window: Option<u64>,
+
+    /// Only store outputs matching this pattern, inputs spending any other
+    /// output won't be resolved. Only the keys of the other outputs are kept,
+    /// to tell them apart from real misses. Misses on outputs left out before
+    /// the keys were tracked, or spent again after the block spending them
+    /// was undone, are still reported as mismatches.
+    pub filter: Option<crosscut::filters::OutputPattern>,
+
+    /// Keep datums published in witness sets or inline in outputs, so that
+    /// datum hashes can be resolved in later blocks. Enabled by default.
+    pub store_datums: Option<bool>,
+
+    /// Start even if the UTxO set can't be verified against the storage cursor
+    /// or the intersect point (eg: with skip storage and a relative intersect).
+    /// Disabled by default.
+    pub skip_reconcile: Option<bool>,
+}
+
+impl Config {
+    pub fn boostrapper(
+        self,
+        engine: kv::Engine,
+        intersect: &crosscut::IntersectConfig,
+        policy: &crosscut::policies::RuntimePolicy,
+    ) -> Bootstrapper {
+        Bootstrapper {
+            config: self,
+            engine,
+            intersect: intersect.clone(),
+            policy: policy.clone(),
+            input: Default::default(),
+            output: Default::default(),
+        }
+    }
+
+    fn rollback_window(&self) -> u64 {
+        self.rollback_window.unwrap_or(DEFAULT_ROLLBACK_WINDOW)
+    }
+
+    fn store_datums(&self) -> bool {
+        self.store_datums.unwrap_or(true)
+    }
+
+    fn skip_reconcile(&self) -> bool {
+        self.skip_reconcile.unwrap_or(false)
+    }
+}
+
+pub struct Bootstrapper {
+    config: Config,
+    engine: kv::Engine,
+    intersect: crosscut::IntersectConfig,
+    policy: crosscut::policies::RuntimePolicy,
+    input: InputPort,
+    output: OutputPort,
+}
+
+impl Bootstrapper {
+    /// Fails unless the config opts into starting from an unverified UTxO set
+    fn unverified(&self, reason: String) -> Result<(), crate::Error> {
+        if self.config.skip_reconcile() {
+            log::warn!("{}, starting anyway", reason);
+            return Ok(());
+        }
+
+        Err(crate::Error::config(format!(
+            "{}, set skip_reconcile to start anyway",
+            reason
+        )))
+    }
+
+    /// Makes sure that the UTxO set reflects the same point of the chain that
+    /// storage will resume from, undoing blocks if the UTxO set is ahead.
+    /// Refuses to continue if the UTxO set can't be reconciled.
+    pub fn reconcile(&self, storage_point: Option<crosscut::PointArg>) -> Result<(), crate::Error> {
+        // the db is released at the end of the scope so that the worker can
+        // open it again
+        let stores = Stores::open(self.engine, &self.config.db_path)?;
+        let enrich_point = stores.read_cursor()?;
+
+        let enrich_point: Point = match enrich_point {
+            Some(x) => x.try_into()?,
+            None if stores.db.is_empty(Table::Utxos)? => {
+                return match storage_point {
+                    Some(x) => Err(crate::Error::config(format!(
+                        "enrich db is empty but storage cursor is at {}, the enrich db needs to be rebuilt",
+                        x.to_string()
+                    ))),
+                    None => Ok(()),
+                };
+            }
+            None => {
+                return self
+                    .unverified("enrich db has no cursor to verify it against storage".into())
+            }
+        };
+
+        let target: Point = match (storage_point, self.intersect.get_point()) {
+            (Some(x), _) => x.try_into()?,
+            (None, Some(x)) => x,
+            (None, None) => {
+                return self.unverified(format!(
+                    "enrich db is at {:?} but there's no storage cursor nor intersect point to verify it",
+                    enrich_point
+                ))
+            }
+        };
+
+        log::info!(
+            "reconciling enrich db at {:?} with start point {:?}",
+            enrich_point,
+            target
+        );
+
+        if target == enrich_point {
+            return Ok(());
+        }
+
+        if target.slot_or_default() >= enrich_point.slot_or_default() {
+            return Err(crate::Error::config(format!(
+                "enrich db at {:?} is behind or on a different fork than start point {:?}",
+                enrich_point, target
+            )));
+        }
+
+        if target.slot_or_default() < stores.read_undo_floor()? {
+            return Err(crate::Error::config(format!(
+                "enrich db at {:?} can't be rolled back to {:?}, it's beyond the undo window",
+                enrich_point, target
+            )));
+        }
+
+        let undone = stores.undo_blocks(&target)?;
+        stores.write_cursor(&target)?;
+        stores.db.flush()?;
+
+        log::warn!(
+            "rolled back {} blocks of the enrich db to match start point {:?}",
+            undone,
+            target
+        );
+
+        Ok(())
+    }
+
+    pub fn borrow_input_port(&mut self) -> &'_ mut InputPort {
+        &mut self.input
+    }
+
+    pub fn borrow_output_port(&mut self) -> &'_ mut OutputPort {
+        &mut self.output
+    }
+
+    pub fn spawn_stages(self, pipeline: &mut bootstrap::Pipeline) {
+        let worker = Worker {
+            config: self.config,
+            engine: self.engine,
+            policy: self.policy,
+            stores: None,
+            undo_count: 0,
+            input: self.input,
+            output: self.output,
+            inserts_counter: Default::default(),
+            remove_counter: Default::default(),
+            matches_counter: Default::default(),
+            mismatches_counter: Default::default(),
+            filtered_counter: Default::default(),
+            blocks_counter: Default::default(),
+            fetch_metrics: Default::default(),
+            db_size: Default::default(),
+            utxos_count: Default::default(),
+            last_db_stats: None,
+        };
+
+        pipeline.register_stage(spawn_stage(
+            worker,
+            gasket::runtime::Policy {
+                tick_timeout: Some(Duration::from_secs(600)),
+                ..Default::default()
+            },
+            Some("enrich-local"),
+        ));
+    }
+}
+
+pub struct Worker {
+    config: Config,
+    engine: kv::Engine,
+    policy: crosscut::policies::RuntimePolicy,
+    stores: Option<Stores>,
+    undo_count: u64,
+    input: InputPort,
+    output: OutputPort,
+    inserts_counter: gasket::metrics::Counter,
+    remove_counter: gasket::metrics::Counter,
+    matches_counter: gasket::metrics::Counter,
+    mismatches_counter: gasket::metrics::Counter,
+    filtered_counter: gasket::metrics::Counter,
+    blocks_counter: gasket::metrics::Counter,
+    fetch_metrics: FetchMetrics,
+    db_size: gasket::metrics::Gauge,
+    utxos_count: gasket::metrics::Gauge,
+    last_db_stats: Option<Instant>,
+}
+
+/// Length of the binary key of a UTxO: tx hash followed by the output index
+/// as a big-endian u16
+const UTXO_KEY_LEN: usize = 34;
+
+fn utxo_key(tx_hash: &Hash<32>, index: u64) -> [u8; UTXO_KEY_LEN] {
+    let mut key = [0u8; UTXO_KEY_LEN];
+    key[..32].copy_from_slice(&tx_hash[..]);
+
+    // tx size limits keep output indexes way below u16::MAX
+    key[32..].copy_from_slice(&(index as u16).to_be_bytes());
+
+    key
+}
+
+fn utxo_key_from_ref(utxo_ref: &OutputRef) -> [u8; UTXO_KEY_LEN] {
+    utxo_key(utxo_ref.hash(), utxo_ref.index())
+}
+
+/// Parses the `{hash}#{idx}` keys used by the legacy format
+fn utxo_key_from_legacy(key: &[u8]) -> Result<[u8; UTXO_KEY_LEN], crate::Error> {
+    let text = std::str::from_utf8(key).map_err(crate::Error::storage)?;
+
+    let (hash, index) = text
+        .split_once('#')
+        .ok_or_else(|| crate::Error::storage(format!("invalid legacy utxo key {}", text)))?;
+
+    let hash = Hash::<32>::from_str(hash).map_err(crate::Error::storage)?;
+    let index = index.parse().map_err(crate::Error::storage)?;
+
+    Ok(utxo_key(&hash, index))
+}
+
+/// Version of the on-disk format, bumped whenever keys or values change
+const DB_FORMAT_VERSION: u8 = 1;
+
+/// Prefix of every UTxO value, distinguishes it from the legacy CBOR encoding
+/// which always starts with an array header
+const UTXO_VALUE_VERSION: u8 = 1;
+
+/// Era and CBOR of a tx output, stored as the version byte, the era as a
+/// big-endian u16 and the raw CBOR of the output
+struct UtxoValue(u16, Vec<u8>);
+
+impl From<UtxoValue> for Vec<u8> {
+    fn from(value: UtxoValue) -> Self {
+        let UtxoValue(era, body) = value;
+
+        let mut bytes = Vec::with_capacity(body.len() + 3);
+        bytes.push(UTXO_VALUE_VERSION);
+        bytes.extend_from_slice(&era.to_be_bytes());
+        bytes.extend(body);
+
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for UtxoValue {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value {
+            [UTXO_VALUE_VERSION, era_hi, era_lo, body @ ..] => Ok(UtxoValue(
+                u16::from_be_bytes([*era_hi, *era_lo]),
+                body.to_vec(),
+            )),
+            _ => Err(crate::Error::storage(
+                "unsupported utxo value format, the enrich db needs to be upgraded",
+            )),
+        }
+    }
+}
+
+impl UtxoValue {
+    fn decode_legacy(value: &[u8]) -> Result<Self, crate::Error> {
+        let (era, body): (u16, Vec<u8>) = minicbor::decode(value).map_err(crate::Error::cbor)?;
+
+        Ok(UtxoValue(era, body))
+    }
+}
+
+const UNDO_RECORD_VERSION: u8 = 1;
+
+/// Information required to revert the effects of a block on the UTxO set,
+/// consumed UTxOs are kept as their key and encoded value
+struct UndoRecord {
+    block_hash: Vec<u8>,
+    produced: Vec<Vec<u8>>,
+    consumed: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl TryInto<Vec<u8>> for UndoRecord {
+    type Error = crate::Error;
+
+    fn try_into(self) -> Result<Vec<u8>, Self::Error> {
+        let UndoRecord {
+            block_hash,
+            produced,
+            consumed,
+        } = self;
+
+        let produced: Vec<Bytes> = produced.into_iter().map(Bytes::from).collect();
+
+        let consumed: Vec<(Bytes, Bytes)> = consumed
+            .into_iter()
+            .map(|(k, v)| (Bytes::from(k), Bytes::from(v)))
+            .collect();
+
+        minicbor::to_vec((
+            UNDO_RECORD_VERSION,
+            Bytes::from(block_hash),
+            produced,
+            consumed,
+        ))
+        .map_err(crate::Error::cbor)
+    }
+}
+
+impl TryFrom<&[u8]> for UndoRecord {
+    type Error = crate::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let (version, block_hash, produced, consumed): (
+            u8,
+            Bytes,
+            Vec<Bytes>,
+            Vec<(Bytes, Bytes)>,
+        ) = minicbor::decode(value).map_err(crate::Error::cbor)?;
+
+        if version != UNDO_RECORD_VERSION {
+            return Err(crate::Error::storage(format!(
+                "unsupported undo record version {}",
+                version
+            )));
+        }
+
+        Ok(UndoRecord {
+            block_hash: block_hash.into(),
+            produced: produced.into_iter().map(Into::into).collect(),
+            consumed: consumed
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        })
+    }
+}
+
+impl UndoRecord {
+    /// Decodes a record of the legacy format, which used string keys and a
+    /// CBOR-wrapped value
+    fn decode_legacy(value: &[u8]) -> Result<Self, crate::Error> {
+        let (block_hash, produced, consumed): (Vec<u8>, Vec<String>, Vec<(String, u16, Vec<u8>)>) =
+            minicbor::decode(value).map_err(crate::Error::cbor)?;
+
+        let produced = produced
+            .iter()
+            .map(|k| utxo_key_from_legacy(k.as_bytes()).map(|k| k.to_vec()))
+            .collect::<Result<_, _>>()?;
+
+        let consumed = consumed
+            .into_iter()
+            .map(|(k, era, body)| {
+                let key = utxo_key_from_legacy(k.as_bytes())?;
+                Ok((key.to_vec(), UtxoValue(era, body).into()))
+            })
+            .collect::<Result<_, crate::Error>>()?;
+
+        Ok(UndoRecord {
+            block_hash,
+            produced,
+            consumed,
+        })
+    }
+}
+
+fn undo_key(slot: u64) -> [u8; 8] {
+    slot.to_be_bytes()
+}
+
+fn undo_key_slot(key: &[u8]) -> u64 {
+    let bytes: [u8; 8] = key.try_into().unwrap_or_default();
+    u64::from_be_bytes(bytes)
+}
+
+const META_CURSOR_KEY: &str = "cursor";
+const META_UNDO_FLOOR_KEY: &str = "undo_floor";
+const META_FORMAT_KEY: &str = "format";
+const META_UTXOS_KEY: &str = "utxos";
+
+/// Enrich state on top of the kv store: the `Utxos` table holds the UTxO set,
+/// `Undo` holds per-block undo records keyed by slot and `Meta` holds the last
+/// applied point, the oldest slot that can be undone and the size of the UTxO
+/// set.
+#[derive(Clone)]
+struct Stores {
+    db: kv::Db,
+}
+
+impl Stores {
+    fn open(engine: kv::Engine, path: &str) -> Result<Self, crate::Error> {
+        let db = kv::Db::open(engine, path)?;
+
+        match db.get(Table::Meta, META_FORMAT_KEY.as_bytes())? {
+            Some(x) if x == [DB_FORMAT_VERSION] => (),
+            Some(x) => {
+                return Err(crate::Error::storage(format!(
+                    "enrich db at {} has unsupported format {:?}",
+                    path, x
+                )))
+            }
+            None if db.is_empty(Table::Utxos)? && db.is_empty(Table::Undo)? => {
+                let mut batch = kv::Batch::default();
+                batch.insert(Table::Meta, META_FORMAT_KEY, [DB_FORMAT_VERSION]);
+                Self::insert_utxo_count(&mut batch, 0);
+                db.apply(batch)?;
+            }
+            None => {
+                return Err(crate::Error::storage(format!(
+                    "enrich db at {} uses the legacy format, run `scrolls upgrade-enrich` to migrate it",
+                    path
+                )))
+            }
+        }
+
+        Ok(Self { db })
+    }
+
+    fn read_cursor(&self) -> Result<Option<crosscut::PointArg>, crate::Error> {
+        let value = self.db.get(Table::Meta, META_CURSOR_KEY.as_bytes())?;
+
+        match value {
+            Some(x) => {
+                let text = String::from_utf8(x).map_err(crate::Error::storage)?;
+                crosscut::PointArg::from_str(&text).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn insert_cursor(batch: &mut kv::Batch, point: &Point) {
+        let text = crosscut::PointArg::from(point.clone()).to_string();
+        batch.insert(Table::Meta, META_CURSOR_KEY, text);
+    }
+
+    fn write_cursor(&self, point: &Point) -> Result<(), crate::Error> {
+        let mut batch = kv::Batch::default();
+        Self::insert_cursor(&mut batch, point);
+
+        self.db.apply(batch)
+    }
+
+    fn read_undo_floor(&self) -> Result<u64, crate::Error> {
+        let value = self.db.get(Table::Meta, META_UNDO_FLOOR_KEY.as_bytes())?;
+
+        Ok(value.map(|x| undo_key_slot(&x)).unwrap_or_default())
+    }
+
+    fn write_undo_floor(&self, slot: u64) -> Result<(), crate::Error> {
+        let mut batch = kv::Batch::default();
+        batch.insert(Table::Meta, META_UNDO_FLOOR_KEY, undo_key(slot));
+
+        self.db.apply(batch)
+    }
+
+    /// Amount of UTxOs in the set, kept along every change since counting
+    /// them requires a full scan. Missing on dbs created by older versions.
+    fn read_utxo_count(&self) -> Result<Option<u64>, crate::Error> {
+        let value = self.db.get(Table::Meta, META_UTXOS_KEY.as_bytes())?;
+
+        Ok(value.map(|x| undo_key_slot(&x)))
+    }
+
+    fn insert_utxo_count(batch: &mut kv::Batch, count: u64) {
+        batch.insert(Table::Meta, META_UTXOS_KEY, count.to_be_bytes());
+    }
+
+    fn write_utxo_count(&self, count: u64) -> Result<(), crate::Error> {
+        let mut batch = kv::Batch::default();
+        Self::insert_utxo_count(&mut batch, count);
+
+        self.db.apply(batch)
+    }
+
+    /// Counts the UTxOs of dbs that don't keep track of them yet, which
+    /// requires a full scan but only happens once
+    fn init_utxo_count(&self) -> Result<u64, crate::Error> {
+        if let Some(count) = self.read_utxo_count()? {
+            return Ok(count);
+        }
+
+        log::warn!("counting the utxos of the enrich db, this might take a while");
+
+        let count = self.db.len(Table::Utxos)?;
+        self.write_utxo_count(count)?;
+
+        Ok(count)
+    }
+
+    /// Collects what is needed to revert a block, must be called before
+    /// applying any change to the UTxO set. If the block was already applied
+    /// (eg: it's replayed after a restart) the existing record is kept, since
+    /// the UTxOs it consumed are no longer in the set.
+    fn undo_record(
+        &self,
+        slot: u64,
+        block_hash: &[u8],
+        produced: Vec<Vec<u8>>,
+        consumed: &[Vec<u8>],
+    ) -> Result<UndoRecord, crate::Error> {
+        if let Some(existing) = self.db.get(Table::Undo, &undo_key(slot))? {
+            let existing = UndoRecord::try_from(existing.as_slice())?;
+
+            if existing.block_hash == block_hash {
+                return Ok(existing);
+            }
+        }
+
+        // utxos produced in this same block are not found yet, which is fine
+        // since they didn't exist before the block
+        let mut found = vec![];
+
+        for key in consumed {
+            if let Some(value) = self.db.get(Table::Utxos, key)? {
+                found.push((key.clone(), value));
+            }
+        }
+
+        Ok(UndoRecord {
+            block_hash: block_hash.to_vec(),
+            produced,
+            consumed: found,
+        })
+    }
+
+    /// Writes every change of a block at once: its undo record, the UTxOs it
+    /// produces and consumes, the datums it publishes and the new cursor. A
+    /// crash never leaves them out of sync. The keys of outputs left out by
+    /// the filter are only tracked when there's a filter.
+    fn apply_block(
+        &self,
+        point: &Point,
+        undo: UndoRecord,
+        produced: &[(Vec<u8>, Vec<u8>)],
+        filtered: Option<&[Vec<u8>]>,
+        consumed: &[Vec<u8>],
+        datums: &HashMap<Hash<32>, Vec<u8>>,
+    ) -> Result<(), crate::Error> {
+        let mut batch = kv::Batch::default();
+
+        let cursor = crosscut::PointArg::from(point.clone()).to_string();
+        let replayed =
+            self.db.get(Table::Meta, META_CURSOR_KEY.as_bytes())? == Some(cursor.into_bytes());
+
+        // a replayed block was already counted
+        if let (Some(count), false) = (self.read_utxo_count()?, replayed) {
+            let produced_keys: HashSet<_> = produced.iter().map(|(k, _)| k).collect();
+            let spent_in_block = consumed.iter().filter(|k| produced_keys.contains(k));
+            let removed = undo.consumed.len() + spent_in_block.count();
+
+            let count = (count + produced.len() as u64).saturating_sub(removed as u64);
+            Self::insert_utxo_count(&mut batch, count);
+        }
+
+        let undo: Vec<u8> = undo.try_into()?;
+        batch.insert(Table::Undo, undo_key(point.slot_or_default()), undo);
+
+        for (key, value) in produced.iter() {
+            batch.insert(Table::Utxos, key.as_slice(), value.as_slice());
+        }
+
+        // datums are content-addressed so they are never removed, not even
+        // on rollback
+        for (hash, cbor) in datums.iter() {
+            batch.insert(Table::Datums, hash.to_vec(), cbor.as_slice());
+        }
+
+        for key in filtered.unwrap_or_default() {
+            batch.insert(Table::Filtered, key.as_slice(), vec![]);
+        }
+
+        // removals go last, utxos can be produced and consumed within the
+        // same block
+        for key in consumed.iter() {
+            batch.remove(Table::Utxos, key.as_slice());
+
+            if filtered.is_some() {
+                batch.remove(Table::Filtered, key.as_slice());
+            }
+        }
+
+        Self::insert_cursor(&mut batch, point);
+
+        self.db.apply(batch)
+    }
+
+    /// Discards the oldest undo records, remembering the last slot that can
+    /// still be undone
+    fn prune_undo_records(&self, count: u64) -> Result<(), crate::Error> {
+        for _ in 0..count {
+            if let Some((key, _)) = self.db.pop_first(Table::Undo)? {
+                self.write_undo_floor(undo_key_slot(&key))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reverts the UTxO set to the state it had right after the given point,
+    /// returns the number of blocks undone
+    fn undo_blocks(&self, point: &Point) -> Result<u64, crate::Error> {
+        let from = match point {
+            Point::Specific(slot, _) => undo_key(slot + 1),
+            Point::Origin => undo_key(0),
+        };
+
+        let keys = self.db.keys_from(Table::Undo, &from)?;
+
+        let mut count = self.read_utxo_count()?;
+        let mut undone = 0;
+
+        for key in keys.into_iter().rev() {
+            let record = match self.db.get(Table::Undo, &key)? {
+                Some(x) => UndoRecord::try_from(x.as_slice())?,
+                None => continue,
+            };
+
+            log::debug!(
+                "undoing utxo changes of block {} at slot {}",
+                hex::encode(&record.block_hash),
+                undo_key_slot(&key)
+            );
+
+            let mut batch = kv::Batch::default();
+
+            if let Some(count) = count.as_mut() {
+                let mut removed = 0;
+
+                // produced utxos might have been filtered out or spent
+                for utxo_key in record.produced.iter() {
+                    if self.db.get(Table::Utxos, utxo_key)?.is_some() {
+                        removed += 1;
+                    }
+                }
+
+                *count = (*count + record.consumed.len() as u64).saturating_sub(removed);
+                Self::insert_utxo_count(&mut batch, *count);
+            }
+
+            // restore consumed utxos first, produced ones might have been
+            // consumed within the same block
+            for (utxo_key, value) in record.consumed {
+                batch.insert(Table::Utxos, utxo_key, value);
+            }
+
+            // produced keys include the ones left out by the filter
+            for utxo_key in record.produced {
+                batch.remove(Table::Filtered, utxo_key.as_slice());
+                batch.remove(Table::Utxos, utxo_key);
+            }
+
+            batch.remove(Table::Undo, key);
+
+            self.db.apply(batch)?;
+            undone += 1;
+        }
+
+        Ok(undone)
+    }
+}
+
+/// Amount of UTxOs written to the db on each batch while importing
+const IMPORT_BATCH_SIZE: usize = 10_000;
+
+/// Seeds an empty enrich db with the UTxO set of the ledger at a known point,
+/// so that the pipeline can start from a recent intersect instead of syncing
+/// from origin.
+pub struct Importer {
+    stores: Stores,
+    batch: kv::Batch,
+    imported: u64,
+}
+
+impl Importer {
+    pub fn open(engine: kv::Engine, db_path: &str) -> Result<Self, crate::Error> {
+        let stores = Stores::open(engine, db_path)?;
+
+        if !stores.db.is_empty(Table::Utxos)? || stores.read_cursor()?.is_some() {
+            return Err(crate::Error::config(format!(
+                "enrich db at {} is not empty, refusing to import utxos",
+                db_path
+            )));
+        }
+
+        Ok(Self {
+            stores,
+            batch: Default::default(),
+            imported: 0,
+        })
+    }
+
+    pub fn insert(
+        &mut self,
+        tx_hash: &Hash<32>,
+        index: u64,
+        era: Era,
+        cbor: Vec<u8>,
+    ) -> Result<(), crate::Error> {
+        let value: Vec<u8> = UtxoValue(era.into(), cbor).into();
+        self.batch
+            .insert(Table::Utxos, utxo_key(tx_hash, index), value);
+
+        self.imported += 1;
+
+        if self.batch.len() >= IMPORT_BATCH_SIZE {
+            self.flush_batch()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<(), crate::Error> {
+        let batch = std::mem::take(&mut self.batch);
+
+        self.stores.db.apply(batch)?;
+
+        log::info!("imported {} utxos so far", self.imported);
+
+        Ok(())
+    }
+
+    /// Reads back an imported UTxO, flushing the pending batch first
+    #[cfg(test)]
+    pub(super) fn find_utxo(
+        &mut self,
+        tx_hash: &Hash<32>,
+        index: u64,
+    ) -> Result<Option<(Era, Vec<u8>)>, crate::Error> {
+        self.flush_batch()?;
+
+        let utxo_ref = OutputRef::new(*tx_hash, index);
+        let found = fetch_referenced_utxo(&self.stores.db, &Default::default(), &utxo_ref)?;
+
+        Ok(found.map(|(_, era, cbor)| (era, cbor)))
+    }
+
+    /// Marks the imported UTxO set as the state of the ledger right after the
+    /// given point. Blocks before that point can't be undone since there are
+    /// no undo records for them. Returns the amount of imported UTxOs.
+    pub fn finish(mut self, point: &Point) -> Result<u64, crate::Error> {
+        self.flush_batch()?;
+
+        self.stores.write_cursor(point)?;
+        self.stores.write_undo_floor(point.slot_or_default())?;
+        self.stores.write_utxo_count(self.imported)?;
+
+        self.stores.db.flush()?;
+
+        Ok(self.imported)
+    }
+}
+
+/// Rewrites a db of the legacy format, which used `{hash}#{idx}` string keys
+/// and CBOR-wrapped values, into the current format. Entries already
+/// converted are skipped, so it's safe to run again if interrupted. Returns
+/// the amount of converted UTxOs.
+pub fn upgrade(engine: kv::Engine, db_path: &str) -> Result<u64, crate::Error> {
+    let db = kv::Db::open(engine, db_path)?;
+
+    if let Some(format) = db.get(Table::Meta, META_FORMAT_KEY.as_bytes())? {
+        log::info!("enrich db is already in format {:?}", format);
+        return Ok(0);
+    }
+
+    let mut converted = 0;
+    let mut batch = kv::Batch::default();
+
+    db.scan(Table::Utxos, |key, value| {
+        if key.len() == UTXO_KEY_LEN {
+            return Ok(());
+        }
+
+        let new_key = utxo_key_from_legacy(key)?;
+        let new_value: Vec<u8> = UtxoValue::decode_legacy(value)?.into();
+
+        batch.remove(Table::Utxos, key);
+        batch.insert(Table::Utxos, new_key, new_value);
+        converted += 1;
+
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            db.apply(std::mem::take(&mut batch))?;
+            log::info!("converted {} utxos so far", converted);
+        }
+
+        Ok(())
+    })?;
+
+    db.apply(std::mem::take(&mut batch))?;
+
+    db.scan(Table::Undo, |key, value| {
+        // records of the current format are tagged with their version
+        if UndoRecord::try_from(value).is_ok() {
+            return Ok(());
+        }
+
+        let record: Vec<u8> = UndoRecord::decode_legacy(value)?.try_into()?;
+        batch.insert(Table::Undo, key, record);
+
+        Ok(())
+    })?;
+
+    batch.insert(Table::Meta, META_FORMAT_KEY, [DB_FORMAT_VERSION]);
+    db.apply(batch)?;
+
+    db.flush()?;
+
+    Ok(converted)
+}
+
+/// Keys of every output produced by the txs, whether stored or not
+fn produced_keys(txs: &[MultiEraTx]) -> Vec<Vec<u8>> {
+    txs.iter()
+        .flat_map(|tx| {
+            let hash = tx.hash();
+            tx.produces()
+                .into_iter()
+                .map(move |(idx, _)| utxo_key(&hash, idx as u64).to_vec())
+        })
+        .collect()
+}
+
+fn consumed_keys(txs: &[MultiEraTx]) -> Vec<Vec<u8>> {
+    txs.iter()
+        .flat_map(|tx| tx.consumes())
+        .map(|i| utxo_key_from_ref(&i.output_ref()).to_vec())
+        .collect()
+}
+
+/// Binary key and encoded value of UTxOs not written to the db yet
+type PendingUtxos = HashMap<Vec<u8>, Vec<u8>>;
+
+#[inline]
+fn fetch_referenced_utxo(
+    db: &kv::Db,
+    pending: &PendingUtxos,
+    utxo_ref: &OutputRef,
+) -> Result<Option<(OutputRef, Era, Vec<u8>)>, crate::Error> {
+    let key = utxo_key_from_ref(utxo_ref);
+
+    let value = match pending.get(&key[..]) {
+        Some(x) => Some(x.clone()),
+        None => db.get(Table::Utxos, &key)?,
+    };
+
+    if let Some(value) = value {
+        let UtxoValue(era, cbor) = UtxoValue::try_from(value.as_slice())?;
+        let era: Era = era.try_into().map_err(crate::Error::storage)?;
+        Ok(Some((utxo_ref.clone(), era, cbor)))
+    } else {
+        Ok(None)
+    }
+}
+
+impl Worker {
+    /// Outputs of the block that are kept in the UTxO set, as binary key and
+    /// encoded value, and the keys of the ones left out by the filter (if
+    /// there's any)
+    fn produced_utxos(
+        &self,
+        txs: &[MultiEraTx],
+    ) -> (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<Vec<u8>>>) {
+        let mut produced = vec![];
+        let mut filtered = self.config.filter.as_ref().map(|_| vec![]);
+
+        for tx in txs.iter() {
+            for (idx, output) in tx.produces() {
+                let key = utxo_key(&tx.hash(), idx as u64);
+
+                if let (Some(filter), Some(filtered)) = (&self.config.filter, filtered.as_mut()) {
+                    if !filter.matches(&output) {
+                        filtered.push(key.to_vec());
+                        continue;
+                    }
+                }
+
+                let era = tx.era().into();
+                let body = output.encode();
+                let value: Vec<u8> = UtxoValue(era, body).into();
+
+                produced.push((key.to_vec(), value));
+            }
+        }
+
+        (produced, filtered)
+    }
+
+    /// A miss is only attributed to the filter when the output is known to
+    /// have been left out by it, either in this block or in a previous one
+    fn was_filtered(
+        &self,
+        db: &kv::Db,
+        filtered: &HashSet<&[u8]>,
+        utxo_ref: &OutputRef,
+    ) -> Result<bool, crate::Error> {
+        if self.config.filter.is_none() {
+            return Ok(false);
+        }
+
+        let key = utxo_key_from_ref(utxo_ref);
+
+        if filtered.contains(&key[..]) {
+            return Ok(true);
+        }
+
+        Ok(db.get(Table::Filtered, &key)?.is_some())
+    }
+
+    #[inline]
+    fn par_fetch_referenced_utxos(
+        &mut self,
+        db: &kv::Db,
+        pending: &PendingUtxos,
+        filtered: &HashSet<&[u8]>,
+        txs: &[MultiEraTx],
+    ) -> Result<BlockContext, crate::Error> {
+        let mut ctx = BlockContext::default();
+
+        let required: Vec<_> = txs.iter().flat_map(model::required_txos).collect();
+
+        let matches: Result<Vec<_>, crate::Error> = required
+            .par_iter()
+            .map(|utxo_ref| fetch_referenced_utxo(db, pending, utxo_ref))
+            .collect();
+
+        let mut hits = 0;
+
+        for (utxo_ref, m) in required.iter().zip(matches?) {
+            if let Some((key, era, cbor)) = m {
+                ctx.import_ref_output(&key, era, cbor);
+                self.matches_counter.inc(1);
+                hits += 1;
+            } else if self.was_filtered(db, filtered, utxo_ref)? {
+                self.filtered_counter.inc(1);
+            } else {
+                self.mismatches_counter.inc(1);
+            }
+        }
+
+        self.fetch_metrics
+            .record_lookups(hits, required.len() as u64);
+
+        Ok(ctx)
+    }
+
+    /// Datums that become known in this block, by hash
+    fn block_datums(
+        &self,
+        block: &MultiEraBlock,
+        txs: &[MultiEraTx],
+    ) -> Result<HashMap<Hash<32>, Vec<u8>>, crate::Error> {
+        let inline = txs
+            .iter()
+            .flat_map(|tx| tx.produces())
+            .filter_map(|(_, output)| model::inline_datum(&output))
+            .map(|datum| (datum.compute_hash(), datum));
+
+        model::witness_datums(block)
+            .into_iter()
+            .chain(inline)
+            .map(|(hash, datum)| {
+                let cbor = minicbor::to_vec(&datum).map_err(crate::Error::cbor)?;
+                Ok((hash, cbor))
+            })
+            .collect()
+    }
+
+    /// Resolves the datums referenced by hash from the outputs produced or
+    /// required by the block, either from this same block or from the db
+    fn fetch_referenced_datums(
+        &self,
+        db: &kv::Db,
+        block_datums: &HashMap<Hash<32>, Vec<u8>>,
+        txs: &[MultiEraTx],
+        ctx: &mut BlockContext,
+    ) -> Result<(), crate::Error> {
+        let produced = txs
+            .iter()
+            .flat_map(|tx| tx.produces())
+            .filter_map(|(_, output)| model::datum_hash(&output));
+
+        let required = txs
+            .iter()
+            .flat_map(model::required_txos)
+            .filter_map(|r| ctx.find_utxo(&r).ok().and_then(|u| model::datum_hash(&u)));
+
+        let hashes: HashSet<_> = produced.chain(required).collect();
+
+        for hash in hashes {
+            let cbor = match block_datums.get(&hash) {
+                Some(x) => Some(x.clone()),
+                None => db.get(Table::Datums, &hash[..])?,
+            };
+
+            if let Some(cbor) = cbor {
+                ctx.import_datum(hash, cbor);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn refresh_db_stats(&mut self, stores: &Stores) -> Result<(), crate::Error> {
+        if let Some(count) = stores.read_utxo_count()? {
+            self.utxos_count.set(count as i64);
+        }
+
+        if let Some(last) = self.last_db_stats {
+            if last.elapsed() < DB_STATS_INTERVAL {
+                return Ok(());
+            }
+        }
+
+        self.db_size.set(stores.db.size_on_disk()? as i64);
+        self.last_db_stats = Some(Instant::now());
+
+        Ok(())
+    }
+}
+
+impl gasket::runtime::Worker for Worker {
+    fn metrics(&self) -> gasket::metrics::Registry {
+        let builder = gasket::metrics::Builder::new()
+            .with_counter("enrich_inserts", &self.inserts_counter)
+            .with_counter("enrich_removes", &self.remove_counter)
+            .with_counter("enrich_matches", &self.matches_counter)
+            .with_counter("enrich_mismatches", &self.mismatches_counter)
+            .with_counter("enrich_filtered_misses", &self.filtered_counter)
+            .with_counter("enrich_blocks", &self.blocks_counter)
+            .with_gauge("enrich_db_size", &self.db_size)
+            .with_gauge("enrich_utxos", &self.utxos_count);
+
+        self.fetch_metrics.register(builder).build()
+    }
+
+    fn work(&mut self) -> gasket::runtime::WorkResult {
+        let msg = self.input.recv_or_idle()?;
+
+        match msg.payload {
+            model::RawBlockPayload::RollForward(cbor) => {
+                let block = MultiEraBlock::decode(&cbor)
+                    .map_err(crate::Error::cbor)
+                    .apply_policy(&self.policy)
+                    .or_panic()?;
+
+                let block = match block {
+                    Some(x) => x,
+                    None => return Ok(gasket::runtime::WorkOutcome::Partial),
+                };
+
+                let stores = self.stores.clone().unwrap();
+                let db = &stores.db;
+
+                let txs = block.txs();
+                let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+                let consumed = consumed_keys(&txs);
+
+                // keep what we need to revert this block in case of a rollback
+                let undo = stores
+                    .undo_record(
+                        block.slot(),
+                        &block.hash()[..],
+                        produced_keys(&txs),
+                        &consumed,
+                    )
+                    .or_restart()?;
+
+                let (produced, filtered) = self.produced_utxos(&txs);
+
+                // utxos not in the db yet: the ones produced by this block and,
+                // if the block is being replayed, the ones it already consumed
+                let pending: PendingUtxos = produced
+                    .iter()
+                    .chain(undo.consumed.iter())
+                    .cloned()
+                    .collect();
+
+                let filtered_in_block: HashSet<&[u8]> =
+                    filtered.iter().flatten().map(|k| k.as_slice()).collect();
+
+                let fetch_start = Instant::now();
+                let mut ctx = self
+                    .par_fetch_referenced_utxos(db, &pending, &filtered_in_block, &txs)
+                    .or_restart()?;
+
+                let mut datums = HashMap::new();
+
+                if self.config.store_datums() {
+                    datums = self.block_datums(&block, &txs).or_restart()?;
+                    self.fetch_referenced_datums(db, &datums, &txs, &mut ctx)
+                        .or_restart()?;
+                }
+
+                self.fetch_metrics.record_latency(fetch_start.elapsed());
+
+                stores
+                    .apply_block(
+                        &point,
+                        undo,
+                        &produced,
+                        filtered.as_deref(),
+                        &consumed,
+                        &datums,
+                    )
+                    .or_restart()?;
+
+                self.inserts_counter.inc(produced.len() as u64);
+                self.remove_counter.inc(consumed.len() as u64);
+
+                self.undo_count += 1;
+
+                let window = self.config.rollback_window();
+
+                if self.undo_count > window {
+                    stores
+                        .prune_undo_records(self.undo_count - window)
+                        .or_restart()?;
+
+                    self.undo_count = window;
+                }
+
+                self.output
+                    .send(model::EnrichedBlockPayload::roll_forward(cbor, ctx))?;
+
+                self.blocks_counter.inc(1);
+
+                self.refresh_db_stats(&stores).or_restart()?;
+            }
+            model::RawBlockPayload::RollBack(x) => {
+                let stores = self.stores.clone().unwrap();
+
+                if x.slot_or_default() < stores.read_undo_floor().or_restart()? {
+                    log::error!(
+                        "rollback to {:?} goes beyond the undo window, utxo set might be inconsistent",
+                        x
+                    );
+                }
+
+                // revert the utxo set to the state it had at the rollback point
+                let undone = stores.undo_blocks(&x).or_restart()?;
+                self.undo_count = self.undo_count.saturating_sub(undone);
+
+                stores.write_cursor(&x).or_restart()?;
+
+                self.output
+                    .send(model::EnrichedBlockPayload::roll_back(x))?;
+
+                self.refresh_db_stats(&stores).or_restart()?;
+            }
+        };
+
+        self.input.commit();
+        Ok(WorkOutcome::Partial)
+    }
+
+    fn bootstrap(&mut self) -> Result<(), gasket::error::Error> {
+        let stores = Stores::open(self.engine, &self.config.db_path).or_retry()?;
+
+        self.undo_count = stores.db.len(Table::Undo).or_retry()?;
+        stores.init_utxo_count().or_retry()?;
+        self.refresh_db_stats(&stores).or_retry()?;
+        self.stores = Some(stores);
+
+        Ok(())
+    }
+
+    fn teardown(&mut self) -> Result<(), gasket::error::Error> {
+        match &self.stores {
+            Some(stores) => {
+                stores.db.flush().or_panic()?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TX_HASH: &str = "1e34fd6f7bba8e0e8a5bb7bb4fc6c1a98a0c7d40e5f0ac2fb4e0dc3a1e6e35d9";
+
+    #[test]
+    fn legacy_keys_match_binary_keys() {
+        let hash = Hash::<32>::from_str(TX_HASH).unwrap();
+        let legacy = format!("{}#{}", TX_HASH, 3);
+
+        let key = utxo_key_from_legacy(legacy.as_bytes()).unwrap();
+
+        assert_eq!(key, utxo_key(&hash, 3));
+        assert_eq!(&key[..32], &hash[..]);
+        assert_eq!(&key[32..], &[0, 3]);
+    }
+
+    #[test]
+    fn legacy_values_are_rejected() {
+        let legacy = minicbor::to_vec((5u16, vec![1u8, 2, 3])).unwrap();
+        assert!(UtxoValue::try_from(legacy.as_slice()).is_err());
+
+        let UtxoValue(era, body) = UtxoValue::decode_legacy(&legacy).unwrap();
+        let current: Vec<u8> = UtxoValue(era, body).into();
+
+        let UtxoValue(era, body) = UtxoValue::try_from(current.as_slice()).unwrap();
+        assert_eq!(era, 5);
+        assert_eq!(body, vec![1, 2, 3]);
+    }
+
+    const ENGINES: [kv::Engine; 2] = [kv::Engine::Sled, kv::Engine::Redb];
+
+    fn temp_db(name: &str, engine: kv::Engine) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "scrolls-enrich-{}-{:?}-{}",
+            name,
+            engine,
+            std::process::id()
+        ));
+
+        // sled uses a directory, redb a single file
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+
+        path.to_string_lossy().into_owned()
+    }
+
+    fn point(slot: u64) -> Point {
+        Point::Specific(slot, vec![slot as u8; 32])
+    }
+
+    /// Key of the output `idx` of a fake tx identified by `id`
+    fn key(id: u8, idx: u64) -> Vec<u8> {
+        utxo_key(&Hash::new([id; 32]), idx).to_vec()
+    }
+
+    fn value(key: &[u8]) -> Vec<u8> {
+        UtxoValue(5, key.to_vec()).into()
+    }
+
+    /// Applies a block the same way the worker does
+    fn apply(stores: &Stores, slot: u64, produced: &[Vec<u8>], consumed: &[Vec<u8>]) {
+        let point = point(slot);
+
+        let undo = stores
+            .undo_record(slot, &vec![slot as u8; 32], produced.to_vec(), consumed)
+            .unwrap();
+
+        let produced: Vec<_> = produced.iter().map(|k| (k.clone(), value(k))).collect();
+
+        stores
+            .apply_block(&point, undo, &produced, None, consumed, &HashMap::new())
+            .unwrap();
+    }
+
+    /// Three blocks: 10 creates A#0 and A#1, 20 spends A#0 into B#0, 30 spends
+    /// B#0 and A#1 into C#0 and C#1, which is spent in the same block
+    fn apply_fixture(stores: &Stores) {
+        apply(stores, 10, &[key(0xa, 0), key(0xa, 1)], &[]);
+        apply(stores, 20, &[key(0xb, 0)], &[key(0xa, 0)]);
+
+        apply(
+            stores,
+            30,
+            &[key(0xc, 0), key(0xc, 1)],
+            &[key(0xb, 0), key(0xa, 1), key(0xc, 1)],
+        );
+    }
+
+    fn utxos(stores: &Stores) -> Vec<Vec<u8>> {
+        stores.db.keys_from(Table::Utxos, &[]).unwrap()
+    }
+
+    fn undo_slots(stores: &Stores) -> Vec<u64> {
+        let keys = stores.db.keys_from(Table::Undo, &[]).unwrap();
+        keys.iter().map(|k| undo_key_slot(k)).collect()
+    }
+
+    #[test]
+    fn filtered_keys_follow_the_utxo_set() {
+        for engine in ENGINES {
+            let stores = Stores::open(engine, &temp_db("filtered", engine)).unwrap();
+
+            // like `apply`, but keeping only the outputs in `kept`
+            let apply_filtered =
+                |slot: u64, kept: &[Vec<u8>], left: &[Vec<u8>], consumed: &[Vec<u8>]| {
+                    let produced = [kept, left].concat();
+                    let undo = stores
+                        .undo_record(slot, &vec![slot as u8; 32], produced, consumed)
+                        .unwrap();
+
+                    let kept: Vec<_> = kept.iter().map(|k| (k.clone(), value(k))).collect();
+
+                    stores
+                        .apply_block(
+                            &point(slot),
+                            undo,
+                            &kept,
+                            Some(left),
+                            consumed,
+                            &HashMap::new(),
+                        )
+                        .unwrap();
+                };
+
+            let filtered = || stores.db.keys_from(Table::Filtered, &[]).unwrap();
+
+            apply_filtered(10, &[key(0xa, 0)], &[key(0xa, 1)], &[]);
+            assert_eq!(utxos(&stores), vec![key(0xa, 0)]);
+            assert_eq!(filtered(), vec![key(0xa, 1)]);
+
+            apply_filtered(20, &[], &[], &[key(0xa, 1)]);
+            assert!(filtered().is_empty());
+
+            apply_filtered(30, &[], &[key(0xb, 0)], &[]);
+            assert_eq!(filtered(), vec![key(0xb, 0)]);
+
+            assert_eq!(stores.undo_blocks(&point(20)).unwrap(), 1);
+            assert!(filtered().is_empty());
+            assert_eq!(utxos(&stores), vec![key(0xa, 0)]);
+        }
+    }
+
+    #[test]
+    fn undo_restores_utxo_set() {
+        for engine in ENGINES {
+            let stores = Stores::open(engine, &temp_db("undo", engine)).unwrap();
+
+            apply_fixture(&stores);
+
+            assert_eq!(utxos(&stores), vec![key(0xc, 0)]);
+            assert_eq!(undo_slots(&stores), vec![10, 20, 30]);
+            assert_eq!(stores.read_utxo_count().unwrap(), Some(1));
+
+            assert_eq!(stores.undo_blocks(&point(10)).unwrap(), 2);
+
+            assert_eq!(utxos(&stores), vec![key(0xa, 0), key(0xa, 1)]);
+            assert_eq!(undo_slots(&stores), vec![10]);
+            assert_eq!(stores.read_utxo_count().unwrap(), Some(2));
+
+            let restored = stores.db.get(Table::Utxos, &key(0xa, 1)).unwrap();
+            assert_eq!(restored, Some(value(&key(0xa, 1))));
+
+            // nothing left to undo after the point
+            assert_eq!(stores.undo_blocks(&point(10)).unwrap(), 0);
+
+            assert_eq!(stores.undo_blocks(&Point::Origin).unwrap(), 1);
+            assert!(utxos(&stores).is_empty());
+            assert_eq!(stores.read_utxo_count().unwrap(), Some(0));
+        }
+    }
+
+    #[test]
+    fn replayed_block_keeps_undo_record() {
+        for engine in ENGINES {
+            let stores = Stores::open(engine, &temp_db("replay", engine)).unwrap();
+
+            apply(&stores, 10, &[key(0xa, 0)], &[]);
+            apply(&stores, 20, &[key(0xb, 0)], &[key(0xa, 0)]);
+
+            // A#0 is no longer in the set, the record of the first attempt
+            // is the only one that knows about it
+            apply(&stores, 20, &[key(0xb, 0)], &[key(0xa, 0)]);
+            assert_eq!(stores.read_utxo_count().unwrap(), Some(1));
+
+            assert_eq!(stores.undo_blocks(&point(10)).unwrap(), 1);
+            assert_eq!(utxos(&stores), vec![key(0xa, 0)]);
+        }
+    }
+
+    #[test]
+    fn pruning_moves_undo_floor() {
+        for engine in ENGINES {
+            let stores = Stores::open(engine, &temp_db("prune", engine)).unwrap();
+
+            apply_fixture(&stores);
+            assert_eq!(stores.read_undo_floor().unwrap(), 0);
+
+            stores.prune_undo_records(2).unwrap();
+
+            assert_eq!(undo_slots(&stores), vec![30]);
+            assert_eq!(stores.read_undo_floor().unwrap(), 20);
+
+            // blocks past the floor can't be undone, only the state right
+            // after the floor can be restored
+            assert_eq!(stores.undo_blocks(&point(10)).unwrap(), 1);
+            assert_eq!(utxos(&stores), vec![key(0xa, 1), key(0xb, 0)]);
+
+            // pruning more than what's left keeps the last floor
+            stores.prune_undo_records(5).unwrap();
+            assert_eq!(stores.read_undo_floor().unwrap(), 20);
+        }
+    }
+
+    fn bootstrapper(engine: kv::Engine, db_path: &str) -> Bootstrapper {
+        let config = Config {
+            db_path: db_path.to_owned(),
+            rollback_window: None,
+            filter: None,
+            store_datums: None,
+            skip_reconcile: None,
+        };
+
+        config.boostrapper(
+            engine,
+            &crosscut::IntersectConfig::Origin,
+            &Default::default(),
+        )
+    }
+
+    #[test]
+    fn reconcile_rolls_back_to_storage_cursor() {
+        for engine in ENGINES {
+            let path = temp_db("reconcile", engine);
+
+            // the db needs to be released for the bootstrapper to open it
+            {
+                let stores = Stores::open(engine, &path).unwrap();
+                apply_fixture(&stores);
+                let cursor = stores.read_cursor().unwrap().unwrap();
+                assert_eq!(
+                    cursor.to_string(),
+                    "30,".to_owned() + &hex::encode([30; 32])
+                );
+            }
+
+            let bootstrapper = bootstrapper(engine, &path);
+
+            // storage can't be ahead of the enrich db
+            let ahead = crosscut::PointArg::from(point(40));
+            assert!(bootstrapper.reconcile(Some(ahead)).is_err());
+
+            let target = crosscut::PointArg::from(point(20));
+            bootstrapper.reconcile(Some(target.clone())).unwrap();
+
+            // reconciling again is a no-op
+            bootstrapper.reconcile(Some(target.clone())).unwrap();
+
+            let stores = Stores::open(engine, &path).unwrap();
+            let cursor = stores.read_cursor().unwrap().unwrap();
+
+            assert_eq!(cursor.to_string(), target.to_string());
+            assert_eq!(utxos(&stores), vec![key(0xa, 1), key(0xb, 0)]);
+            assert_eq!(undo_slots(&stores), vec![10, 20]);
+        }
+    }
+
+    #[test]
+    fn reconcile_refuses_beyond_undo_window() {
+        for engine in ENGINES {
+            let path = temp_db("reconcile-floor", engine);
+
+            {
+                let stores = Stores::open(engine, &path).unwrap();
+                apply_fixture(&stores);
+                stores.prune_undo_records(2).unwrap();
+            }
+
+            let bootstrapper = bootstrapper(engine, &path);
+            let target = crosscut::PointArg::from(point(10));
+
+            assert!(bootstrapper.reconcile(Some(target)).is_err());
+
+            // the db is left untouched
+            let stores = Stores::open(engine, &path).unwrap();
+            assert_eq!(utxos(&stores), vec![key(0xc, 0)]);
+        }
+    }
+
+    #[test]
+    fn reconcile_refuses_empty_db_with_storage_cursor() {
+        for engine in ENGINES {
+            let path = temp_db("reconcile-empty", engine);
+            let bootstrapper = bootstrapper(engine, &path);
+
+            bootstrapper.reconcile(None).unwrap();
+
+            let storage = crosscut::PointArg::from(point(10));
+            assert!(bootstrapper.reconcile(Some(storage)).is_err());
+        }
+    }
+
+    #[test]
+    fn reconcile_refuses_unverifiable_start() {
+        for engine in ENGINES {
+            let path = temp_db("reconcile-unverified", engine);
+
+            {
+                let stores = Stores::open(engine, &path).unwrap();
+                apply_fixture(&stores);
+            }
+
+            // no storage cursor and the intersect is relative to the chain
+            let mut bootstrapper = bootstrapper(engine, &path);
+            assert!(bootstrapper.reconcile(None).is_err());
+
+            bootstrapper.config.skip_reconcile = Some(true);
+            bootstrapper.reconcile(None).unwrap();
+
+            // the db is left untouched
+            let stores = Stores::open(engine, &path).unwrap();
+            assert_eq!(undo_slots(&stores), vec![10, 20, 30]);
+        }
+    }
+}
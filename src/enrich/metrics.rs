@@ -0,0 +1,65 @@
+//! Metrics shared by the enrich stages that resolve the inputs of each block
+//! from a store (local db or remote api).
+
+use std::time::Duration;
+
+use gasket::metrics::{Builder, Counter, Gauge};
+
+/// Upper bounds (in microseconds) of the buckets of the fetch latency histogram
+const LATENCY_BUCKETS: [u64; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+/// Gasket only supports counters and gauges, so the latency histogram is kept
+/// as cumulative bucket counters, same as Prometheus does. Blocks slower than
+/// the last bucket are the difference with `enrich_blocks`.
+#[derive(Default)]
+pub struct FetchMetrics {
+    last_latency: Gauge,
+    total_time: Counter,
+    buckets: [Counter; 4],
+    lookups: Counter,
+    hit_ratio: Gauge,
+    total_lookups: u64,
+    total_hits: u64,
+}
+
+impl FetchMetrics {
+    /// Tracks the time it took to resolve the inputs of a block
+    pub fn record_latency(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+
+        self.last_latency.set(micros as i64);
+        self.total_time.inc(micros);
+
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(self.buckets.iter()) {
+            if micros <= *bound {
+                bucket.inc(1);
+            }
+        }
+    }
+
+    /// Tracks how many of the inputs looked up were served without a miss,
+    /// the ratio is a percentage over the whole run
+    pub fn record_lookups(&mut self, hits: u64, total: u64) {
+        self.lookups.inc(total);
+
+        self.total_hits += hits;
+        self.total_lookups += total;
+
+        if self.total_lookups > 0 {
+            let ratio = self.total_hits * 100 / self.total_lookups;
+            self.hit_ratio.set(ratio as i64);
+        }
+    }
+
+    pub fn register(&self, builder: Builder) -> Builder {
+        builder
+            .with_gauge("enrich_fetch_latency_us", &self.last_latency)
+            .with_counter("enrich_fetch_time_us", &self.total_time)
+            .with_counter("enrich_fetch_le_1ms", &self.buckets[0])
+            .with_counter("enrich_fetch_le_10ms", &self.buckets[1])
+            .with_counter("enrich_fetch_le_100ms", &self.buckets[2])
+            .with_counter("enrich_fetch_le_1s", &self.buckets[3])
+            .with_counter("enrich_lookups", &self.lookups)
+            .with_gauge("enrich_hit_ratio", &self.hit_ratio)
+    }
+}
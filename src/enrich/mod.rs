@@ -1,5 +1,9 @@
+pub mod kv;
+pub mod local;
+pub mod metrics;
+pub mod remote;
 pub mod skip;
-pub mod sled;
+pub mod snapshot;
 
 use gasket::messaging::{OutputPort, TwoPhaseInputPort};
 use serde::Deserialize;
@@ -10,7 +14,9 @@ use crate::{bootstrap, crosscut, model};
 #[serde(tag = "type")]
 pub enum Config {
     Skip,
-    Sled(sled::Config),
+    Sled(local::Config),
+    Redb(local::Config),
+    Remote(remote::Config),
 }
 
 impl Default for Config {
@@ -20,38 +26,63 @@ impl Default for Config {
 }
 
 impl Config {
-    pub fn bootstrapper(self, policy: &crosscut::policies::RuntimePolicy) -> Bootstrapper {
+    pub fn bootstrapper(
+        self,
+        intersect: &crosscut::IntersectConfig,
+        policy: &crosscut::policies::RuntimePolicy,
+    ) -> Bootstrapper {
         match self {
             Config::Skip => Bootstrapper::Skip(skip::Bootstrapper::default()),
-            Config::Sled(c) => Bootstrapper::Sled(c.boostrapper(policy)),
+            Config::Sled(c) => {
+                Bootstrapper::Local(c.boostrapper(kv::Engine::Sled, intersect, policy))
+            }
+            Config::Redb(c) => {
+                Bootstrapper::Local(c.boostrapper(kv::Engine::Redb, intersect, policy))
+            }
+            Config::Remote(c) => Bootstrapper::Remote(c.boostrapper(policy)),
         }
     }
 }
 
 pub enum Bootstrapper {
     Skip(skip::Bootstrapper),
-    Sled(sled::Bootstrapper),
+    Local(local::Bootstrapper),
+    Remote(remote::Bootstrapper),
 }
 
 impl Bootstrapper {
+    /// Checks that the enrich state matches the point where the pipeline will
+    /// resume from, given the last point persisted by storage
+    pub fn reconcile(&self, storage_point: Option<crosscut::PointArg>) -> Result<(), crate::Error> {
+        match self {
+            Bootstrapper::Skip(_) => Ok(()),
+            Bootstrapper::Local(x) => x.reconcile(storage_point),
+            // the remote api keeps no state of its own
+            Bootstrapper::Remote(_) => Ok(()),
+        }
+    }
+
     pub fn borrow_input_port(&mut self) -> &'_ mut TwoPhaseInputPort<model::RawBlockPayload> {
         match self {
             Bootstrapper::Skip(x) => x.borrow_input_port(),
-            Bootstrapper::Sled(x) => x.borrow_input_port(),
+            Bootstrapper::Local(x) => x.borrow_input_port(),
+            Bootstrapper::Remote(x) => x.borrow_input_port(),
         }
     }
 
     pub fn borrow_output_port(&mut self) -> &'_ mut OutputPort<model::EnrichedBlockPayload> {
         match self {
             Bootstrapper::Skip(x) => x.borrow_output_port(),
-            Bootstrapper::Sled(x) => x.borrow_output_port(),
+            Bootstrapper::Local(x) => x.borrow_output_port(),
+            Bootstrapper::Remote(x) => x.borrow_output_port(),
         }
     }
 
     pub fn spawn_stages(self, pipeline: &mut bootstrap::Pipeline) {
         match self {
             Bootstrapper::Skip(x) => x.spawn_stages(pipeline),
-            Bootstrapper::Sled(x) => x.spawn_stages(pipeline),
+            Bootstrapper::Local(x) => x.spawn_stages(pipeline),
+            Bootstrapper::Remote(x) => x.spawn_stages(pipeline),
         }
     }
 }
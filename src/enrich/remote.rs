@@ -0,0 +1,516 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use gasket::{
+    error::AsWorkError,
+    runtime::{spawn_stage, WorkOutcome},
+};
+use lru::LruCache;
+use pallas::{
+    codec::{minicbor, utils::CborWrap},
+    crypto::hash::Hash,
+    ledger::{
+        primitives::{
+            alonzo::{NativeScript, PlutusData},
+            babbage::{DatumOption, PlutusV1Script, PlutusV2Script, Script},
+        },
+        traverse::{ComputeHash, Era, MultiEraBlock, MultiEraOutput, OutputRef},
+    },
+};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use super::{metrics::FetchMetrics, snapshot};
+use crate::{
+    bootstrap, crosscut,
+    model::{self, BlockContext},
+    prelude::AppliesPolicy,
+};
+
+type InputPort = gasket::messaging::TwoPhaseInputPort<model::RawBlockPayload>;
+type OutputPort = gasket::messaging::OutputPort<model::EnrichedBlockPayload>;
+
+const DEFAULT_CACHE_SIZE: usize = 100_000;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Resolves the UTxOs required by each block through a Kupo-compatible HTTP
+/// api instead of keeping a local copy of the UTxO set. The api needs to index
+/// every output (eg: `kupo --match "*"`), including spent ones.
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    /// base url of the api, eg: `http://localhost:1442`
+    pub url: String,
+
+    /// Amount of outputs (and datums) to keep in memory
+    pub cache_size: Option<usize>,
+
+    /// Max amount of requests in flight at the same time
+    pub max_concurrent_requests: Option<usize>,
+
+    pub timeout_secs: Option<u64>,
+}
+
+impl Config {
+    pub fn boostrapper(self, policy: &crosscut::policies::RuntimePolicy) -> Bootstrapper {
+        Bootstrapper {
+            config: self,
+            policy: policy.clone(),
+            input: Default::default(),
+            output: Default::default(),
+        }
+    }
+}
+
+pub struct Bootstrapper {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+    input: InputPort,
+    output: OutputPort,
+}
+
+impl Bootstrapper {
+    pub fn borrow_input_port(&mut self) -> &'_ mut InputPort {
+        &mut self.input
+    }
+
+    pub fn borrow_output_port(&mut self) -> &'_ mut OutputPort {
+        &mut self.output
+    }
+
+    pub fn spawn_stages(self, pipeline: &mut bootstrap::Pipeline) {
+        let cache_size = NonZeroUsize::new(self.config.cache_size.unwrap_or(DEFAULT_CACHE_SIZE))
+            .unwrap_or(NonZeroUsize::new(DEFAULT_CACHE_SIZE).unwrap());
+
+        let worker = Worker {
+            config: self.config,
+            policy: self.policy,
+            client: None,
+            utxos: LruCache::new(cache_size),
+            datums: LruCache::new(cache_size),
+            input: self.input,
+            output: self.output,
+            matches_counter: Default::default(),
+            mismatches_counter: Default::default(),
+            cache_hits_counter: Default::default(),
+            requests_counter: Default::default(),
+            blocks_counter: Default::default(),
+            fetch_metrics: Default::default(),
+        };
+
+        pipeline.register_stage(spawn_stage(
+            worker,
+            gasket::runtime::Policy {
+                tick_timeout: Some(Duration::from_secs(600)),
+                ..Default::default()
+            },
+            Some("enrich-remote"),
+        ));
+    }
+}
+
+#[derive(Deserialize)]
+struct KupoValue {
+    coins: u64,
+    #[serde(default)]
+    assets: HashMap<String, u64>,
+}
+
+#[derive(Deserialize)]
+struct KupoMatch {
+    transaction_id: String,
+    output_index: u64,
+    address: String,
+    value: KupoValue,
+    datum_hash: Option<String>,
+    datum_type: Option<String>,
+    script_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KupoDatum {
+    datum: String,
+}
+
+#[derive(Deserialize)]
+struct KupoScript {
+    language: String,
+    script: String,
+}
+
+/// Blocking client of a Kupo-compatible api
+pub struct Client {
+    agent: ureq::Agent,
+    url: String,
+    pool: rayon::ThreadPool,
+}
+
+impl Client {
+    pub fn new(
+        url: &str,
+        max_concurrent_requests: usize,
+        timeout: Duration,
+    ) -> Result<Self, crate::Error> {
+        let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(max_concurrent_requests)
+            .build()
+            .map_err(crate::Error::source)?;
+
+        Ok(Self {
+            agent,
+            url: url.trim_end_matches('/').to_owned(),
+            pool,
+        })
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, crate::Error> {
+        self.agent
+            .get(&format!("{}/{}", self.url, path))
+            .call()
+            .map_err(crate::Error::source)?
+            .into_json()
+            .map_err(crate::Error::source)
+    }
+
+    fn fetch_script(&self, hash: &str) -> Result<Option<Script>, crate::Error> {
+        let script: Option<KupoScript> = self.get(&format!("scripts/{}", hash))?;
+
+        let script = match script {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let bytes = snapshot::decode_hex(&script.script)?;
+
+        let script = match script.language.as_str() {
+            "native" => {
+                let native: NativeScript = minicbor::decode(&bytes).map_err(crate::Error::cbor)?;
+                Script::NativeScript(native)
+            }
+            "plutus:v1" => Script::PlutusV1Script(PlutusV1Script(bytes.into())),
+            "plutus:v2" => Script::PlutusV2Script(PlutusV2Script(bytes.into())),
+            x => {
+                return Err(crate::Error::message(format!(
+                    "unknown script language {}",
+                    x
+                )))
+            }
+        };
+
+        Ok(Some(script))
+    }
+
+    /// Returns the CBOR of the datum with the given hash, if known by the api
+    pub fn fetch_datum(&self, hash: &Hash<32>) -> Result<Option<Vec<u8>>, crate::Error> {
+        let datum: Option<KupoDatum> = self.get(&format!("datums/{}", hash))?;
+
+        datum.map(|x| snapshot::decode_hex(&x.datum)).transpose()
+    }
+
+    fn encode_match(&self, m: KupoMatch) -> Result<(OutputRef, Era, Vec<u8>), crate::Error> {
+        let hash = Hash::<32>::from_str(&m.transaction_id).map_err(crate::Error::source)?;
+
+        let mut assets = vec![];
+
+        for (unit, qty) in m.value.assets {
+            let (policy, name) = unit.split_once('.').unwrap_or((&unit, ""));
+            let policy = Hash::<28>::from_str(policy).map_err(crate::Error::source)?;
+            assets.push((policy, snapshot::decode_hex(name)?, qty));
+        }
+
+        let value = snapshot::build_value(m.value.coins, assets);
+
+        let datum = match (m.datum_hash, m.datum_type.as_deref()) {
+            (Some(hash), Some("inline")) => {
+                let hash = Hash::<32>::from_str(&hash).map_err(crate::Error::source)?;
+
+                let cbor = self
+                    .fetch_datum(&hash)?
+                    .ok_or_else(|| crate::Error::missing_datum(hash))?;
+
+                let data: PlutusData = minicbor::decode(&cbor).map_err(crate::Error::cbor)?;
+                Some(DatumOption::Data(CborWrap(data)))
+            }
+            (Some(hash), _) => {
+                let hash = Hash::<32>::from_str(&hash).map_err(crate::Error::source)?;
+                Some(DatumOption::Hash(hash))
+            }
+            (None, _) => None,
+        };
+
+        let script = match m.script_hash {
+            Some(x) => self.fetch_script(&x)?,
+            None => None,
+        };
+
+        let cbor = snapshot::encode_output(&m.address, value, datum, script)?;
+
+        Ok((OutputRef::new(hash, m.output_index), Era::Babbage, cbor))
+    }
+
+    /// Fetches every output of each of the given txs, one request per tx
+    /// with up to `max_concurrent_requests` running in parallel
+    pub fn fetch_tx_outputs(
+        &self,
+        tx_hashes: &[Hash<32>],
+    ) -> Result<Vec<(OutputRef, Era, Vec<u8>)>, crate::Error> {
+        let batches: Result<Vec<_>, crate::Error> = self.pool.install(|| {
+            tx_hashes
+                .par_iter()
+                .map(|hash| {
+                    let matches: Vec<KupoMatch> = self.get(&format!("matches/*@{}", hash))?;
+
+                    matches
+                        .into_iter()
+                        .map(|m| self.encode_match(m))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect()
+        });
+
+        Ok(batches?.into_iter().flatten().collect())
+    }
+
+    /// Fetches the CBOR of each of the given datums, one request per datum
+    /// with up to `max_concurrent_requests` running in parallel
+    pub fn fetch_datums(
+        &self,
+        hashes: &[Hash<32>],
+    ) -> Result<Vec<(Hash<32>, Option<Vec<u8>>)>, crate::Error> {
+        self.pool.install(|| {
+            hashes
+                .par_iter()
+                .map(|hash| Ok((*hash, self.fetch_datum(hash)?)))
+                .collect()
+        })
+    }
+}
+
+pub struct Worker {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+    client: Option<Client>,
+    utxos: LruCache<String, (Era, Vec<u8>)>,
+    /// `None` for datums unknown to the api, so they aren't requested again
+    datums: LruCache<Hash<32>, Option<Vec<u8>>>,
+    input: InputPort,
+    output: OutputPort,
+    matches_counter: gasket::metrics::Counter,
+    mismatches_counter: gasket::metrics::Counter,
+    cache_hits_counter: gasket::metrics::Counter,
+    requests_counter: gasket::metrics::Counter,
+    blocks_counter: gasket::metrics::Counter,
+    fetch_metrics: FetchMetrics,
+}
+
+impl Worker {
+    fn resolve_utxos(
+        &mut self,
+        block: &MultiEraBlock,
+        ctx: &mut BlockContext,
+    ) -> Result<(), crate::Error> {
+        let txs = block.txs();
+
+        // outputs of this block are cached first since some of them might be
+        // spent within the same block or soon after
+        for tx in txs.iter() {
+            for (idx, output) in tx.produces() {
+                let key = OutputRef::new(tx.hash(), idx as u64).to_string();
+                self.utxos.put(key, (tx.era(), output.encode()));
+            }
+        }
+
+        let required: Vec<_> = txs.iter().flat_map(model::required_txos).collect();
+
+        let mut missing = vec![];
+        let mut hits = 0;
+
+        for utxo_ref in required.iter() {
+            match self.utxos.get(&utxo_ref.to_string()) {
+                Some((era, cbor)) => {
+                    ctx.import_ref_output(utxo_ref, *era, cbor.clone());
+                    self.cache_hits_counter.inc(1);
+                    self.matches_counter.inc(1);
+                    hits += 1;
+                }
+                None => missing.push(utxo_ref),
+            }
+        }
+
+        self.fetch_metrics
+            .record_lookups(hits, required.len() as u64);
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx_hashes: Vec<_> = missing.iter().map(|r| *r.hash()).collect();
+        tx_hashes.sort();
+        tx_hashes.dedup();
+
+        let client = self
+            .client
+            .as_ref()
+            .expect("client initialized on bootstrap");
+        let fetched = client.fetch_tx_outputs(&tx_hashes)?;
+        self.requests_counter.inc(tx_hashes.len() as u64);
+
+        for (utxo_ref, era, cbor) in fetched {
+            self.utxos.put(utxo_ref.to_string(), (era, cbor));
+        }
+
+        for utxo_ref in missing {
+            match self.utxos.get(&utxo_ref.to_string()) {
+                Some((era, cbor)) => {
+                    ctx.import_ref_output(utxo_ref, *era, cbor.clone());
+                    self.matches_counter.inc(1);
+                }
+                None => self.mismatches_counter.inc(1),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_datums(
+        &mut self,
+        block: &MultiEraBlock,
+        ctx: &mut BlockContext,
+    ) -> Result<(), crate::Error> {
+        let witness = model::witness_datums(block);
+
+        for (hash, datum) in witness.iter() {
+            let cbor = minicbor::to_vec(datum).map_err(crate::Error::cbor)?;
+            self.datums.put(*hash, Some(cbor));
+        }
+
+        // only datums referenced by hash need a lookup, inline ones travel
+        // with the output and those in the witness set are in the block
+        let by_hash = |output: &MultiEraOutput| match output.datum() {
+            Some(DatumOption::Hash(hash)) => Some(hash),
+            _ => None,
+        };
+
+        let txs = block.txs();
+
+        let produced = txs
+            .iter()
+            .flat_map(|tx| tx.produces())
+            .filter_map(|(_, output)| by_hash(&output))
+            .filter(|hash| !witness.contains_key(hash));
+
+        let required = txs
+            .iter()
+            .flat_map(model::required_txos)
+            .filter_map(|r| ctx.find_utxo(&r).ok().and_then(|u| by_hash(&u)));
+
+        let mut hashes: Vec<_> = produced.chain(required).collect();
+        hashes.sort();
+        hashes.dedup();
+
+        let mut missing = vec![];
+
+        for hash in hashes {
+            match self.datums.get(&hash) {
+                Some(Some(cbor)) => ctx.import_datum(hash, cbor.clone()),
+                Some(None) => (),
+                None => missing.push(hash),
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let client = self
+            .client
+            .as_ref()
+            .expect("client initialized on bootstrap");
+        let fetched = client.fetch_datums(&missing)?;
+        self.requests_counter.inc(missing.len() as u64);
+
+        for (hash, cbor) in fetched {
+            if let Some(cbor) = &cbor {
+                ctx.import_datum(hash, cbor.clone());
+            }
+
+            self.datums.put(hash, cbor);
+        }
+
+        Ok(())
+    }
+}
+
+impl gasket::runtime::Worker for Worker {
+    fn metrics(&self) -> gasket::metrics::Registry {
+        let builder = gasket::metrics::Builder::new()
+            .with_counter("enrich_matches", &self.matches_counter)
+            .with_counter("enrich_mismatches", &self.mismatches_counter)
+            .with_counter("enrich_cache_hits", &self.cache_hits_counter)
+            .with_counter("enrich_requests", &self.requests_counter)
+            .with_counter("enrich_blocks", &self.blocks_counter);
+
+        self.fetch_metrics.register(builder).build()
+    }
+
+    fn work(&mut self) -> gasket::runtime::WorkResult {
+        let msg = self.input.recv_or_idle()?;
+
+        match msg.payload {
+            model::RawBlockPayload::RollForward(cbor) => {
+                let block = MultiEraBlock::decode(&cbor)
+                    .map_err(crate::Error::cbor)
+                    .apply_policy(&self.policy)
+                    .or_panic()?;
+
+                let block = match block {
+                    Some(x) => x,
+                    None => return Ok(gasket::runtime::WorkOutcome::Partial),
+                };
+
+                let mut ctx = BlockContext::default();
+
+                // the api might be temporarily unavailable, retry the block
+                let fetch_start = Instant::now();
+                self.resolve_utxos(&block, &mut ctx).or_retry()?;
+                self.resolve_datums(&block, &mut ctx).or_retry()?;
+                self.fetch_metrics.record_latency(fetch_start.elapsed());
+
+                self.output
+                    .send(model::EnrichedBlockPayload::roll_forward(cbor, ctx))?;
+
+                self.blocks_counter.inc(1);
+            }
+            model::RawBlockPayload::RollBack(x) => {
+                // cached entries are keyed by content hashes, they remain
+                // valid after a rollback
+                self.output
+                    .send(model::EnrichedBlockPayload::roll_back(x))?;
+            }
+        };
+
+        self.input.commit();
+        Ok(WorkOutcome::Partial)
+    }
+
+    fn bootstrap(&mut self) -> Result<(), gasket::error::Error> {
+        let client = Client::new(
+            &self.config.url,
+            self.config
+                .max_concurrent_requests
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS),
+            Duration::from_secs(self.config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+        )
+        .or_panic()?;
+
+        self.client = Some(client);
+
+        Ok(())
+    }
+}
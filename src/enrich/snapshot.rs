@@ -0,0 +1,585 @@
+//! Loads a UTxO set dumped from the ledger into the enrich db, so that it can
+//! resolve inputs of blocks without syncing from origin.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
+
+use pallas::{
+    codec::{
+        minicbor,
+        utils::{Bytes, CborWrap, KeyValuePairs},
+    },
+    crypto::hash::Hash,
+    ledger::{
+        addresses::{Address, ByronAddress},
+        primitives::{
+            alonzo::{NativeScript, PlutusData, Value},
+            babbage::{
+                DatumOption, PlutusV1Script, PlutusV2Script, PostAlonzoTransactionOutput, Script,
+                TransactionOutput,
+            },
+        },
+        traverse::Era,
+    },
+};
+use serde::{
+    de::{self, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use super::local::Importer;
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum SnapshotFormat {
+    /// output of `cardano-cli query utxo --whole-utxo --out-file`
+    Json,
+    /// CBOR map from `[tx_hash, index]` to transaction output, as returned by
+    /// the `GetUTxOWhole` local-state query
+    Cbor,
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(SnapshotFormat::Json),
+            "cbor" => Ok(SnapshotFormat::Cbor),
+            x => Err(crate::Error::message(format!(
+                "unknown snapshot format {}, expecting `json` or `cbor`",
+                x
+            ))),
+        }
+    }
+}
+
+/// Imports every UTxO in the snapshot file, returns the amount of UTxOs read
+pub fn import_file(
+    path: &Path,
+    format: &SnapshotFormat,
+    importer: &mut Importer,
+) -> Result<u64, crate::Error> {
+    match format {
+        SnapshotFormat::Json => {
+            let file = File::open(path).map_err(|x| crate::Error::config(x.to_string()))?;
+            import_json(BufReader::new(file), importer)
+        }
+        SnapshotFormat::Cbor => {
+            let file = File::open(path).map_err(|x| crate::Error::config(x.to_string()))?;
+            import_cbor(BufReader::new(file), importer)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonScript {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(rename = "cborHex")]
+    cbor_hex: String,
+}
+
+#[derive(Deserialize)]
+struct JsonReferenceScript {
+    script: JsonScript,
+}
+
+#[derive(Deserialize)]
+struct JsonOutput {
+    address: String,
+    value: HashMap<String, serde_json::Value>,
+    datumhash: Option<String>,
+    #[serde(rename = "inlineDatumhash")]
+    inline_datum_hash: Option<String>,
+    #[serde(rename = "inlineDatumRaw")]
+    inline_datum_raw: Option<String>,
+    #[serde(rename = "referenceScript")]
+    reference_script: Option<JsonReferenceScript>,
+}
+
+pub(crate) fn decode_hex(value: &str) -> Result<Vec<u8>, crate::Error> {
+    hex::decode(value).map_err(|x| crate::Error::message(x.to_string()))
+}
+
+/// Decodes a bech32 (Shelley) or base58 (Byron) address into its raw bytes
+pub(crate) fn decode_address(value: &str) -> Result<Vec<u8>, crate::Error> {
+    if let Ok(address) = Address::from_bech32(value) {
+        return Ok(address.to_vec());
+    }
+
+    ByronAddress::from_base58(value)
+        .map(|x| Address::Byron(x).to_vec())
+        .map_err(|x| crate::Error::message(x.to_string()))
+}
+
+fn decode_value(value: HashMap<String, serde_json::Value>) -> Result<Value, crate::Error> {
+    let mut lovelace = 0;
+    let mut assets = vec![];
+
+    for (key, amount) in value {
+        if key == "lovelace" {
+            lovelace = amount
+                .as_u64()
+                .ok_or_else(|| crate::Error::message("invalid lovelace amount"))?;
+
+            continue;
+        }
+
+        let policy =
+            Hash::<28>::from_str(&key).map_err(|x| crate::Error::message(x.to_string()))?;
+
+        let names: HashMap<String, u64> =
+            serde_json::from_value(amount).map_err(|x| crate::Error::message(x.to_string()))?;
+
+        for (name, qty) in names {
+            assets.push((policy, decode_hex(&name)?, qty));
+        }
+    }
+
+    Ok(build_value(lovelace, assets))
+}
+
+/// Groups the native assets by policy into a ledger value
+pub(crate) fn build_value(lovelace: u64, assets: Vec<(Hash<28>, Vec<u8>, u64)>) -> Value {
+    if assets.is_empty() {
+        return Value::Coin(lovelace);
+    }
+
+    let mut policies: Vec<(Hash<28>, Vec<(Bytes, u64)>)> = vec![];
+
+    for (policy, name, qty) in assets {
+        match policies.iter_mut().find(|(p, _)| *p == policy) {
+            Some((_, names)) => names.push((name.into(), qty)),
+            None => policies.push((policy, vec![(name.into(), qty)])),
+        }
+    }
+
+    let policies = policies
+        .into_iter()
+        .map(|(policy, names)| (policy, KeyValuePairs::Def(names)))
+        .collect();
+
+    Value::Multiasset(lovelace, KeyValuePairs::Def(policies))
+}
+
+/// Encodes a Babbage-era output, which can represent outputs of any era
+pub(crate) fn encode_output(
+    address: &str,
+    value: Value,
+    datum_option: Option<DatumOption>,
+    script_ref: Option<Script>,
+) -> Result<Vec<u8>, crate::Error> {
+    let output = TransactionOutput::PostAlonzo(PostAlonzoTransactionOutput {
+        address: decode_address(address)?.into(),
+        value,
+        datum_option,
+        script_ref: script_ref.map(CborWrap),
+    });
+
+    minicbor::to_vec(&output).map_err(crate::Error::cbor)
+}
+
+fn decode_script(script: JsonScript) -> Result<Script, crate::Error> {
+    let cbor = decode_hex(&script.cbor_hex)?;
+
+    match script.kind.as_str() {
+        "PlutusScriptV1" => {
+            let bytes: Bytes = minicbor::decode(&cbor).map_err(crate::Error::cbor)?;
+            Ok(Script::PlutusV1Script(PlutusV1Script(bytes)))
+        }
+        "PlutusScriptV2" => {
+            let bytes: Bytes = minicbor::decode(&cbor).map_err(crate::Error::cbor)?;
+            Ok(Script::PlutusV2Script(PlutusV2Script(bytes)))
+        }
+        "SimpleScript" | "SimpleScriptV1" | "SimpleScriptV2" => {
+            let native: NativeScript = minicbor::decode(&cbor).map_err(crate::Error::cbor)?;
+            Ok(Script::NativeScript(native))
+        }
+        x => Err(crate::Error::message(format!("unknown script type {}", x))),
+    }
+}
+
+fn decode_datum(output: &mut JsonOutput) -> Result<Option<DatumOption>, crate::Error> {
+    if let Some(raw) = output.inline_datum_raw.take() {
+        let data: PlutusData = minicbor::decode(&decode_hex(&raw)?).map_err(crate::Error::cbor)?;
+        return Ok(Some(DatumOption::Data(CborWrap(data))));
+    }
+
+    if let Some(hash) = output.inline_datum_hash.take() {
+        // older versions of the cli don't include the raw inline datum, the
+        // best we can do is to keep track of its hash
+        log::warn!(
+            "inline datum {} without raw value, keeping only its hash",
+            hash
+        );
+        let hash = Hash::<32>::from_str(&hash).map_err(|x| crate::Error::message(x.to_string()))?;
+        return Ok(Some(DatumOption::Hash(hash)));
+    }
+
+    match output.datumhash.take() {
+        Some(hash) => {
+            let hash =
+                Hash::<32>::from_str(&hash).map_err(|x| crate::Error::message(x.to_string()))?;
+            Ok(Some(DatumOption::Hash(hash)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn encode_json_output(mut output: JsonOutput) -> Result<Vec<u8>, crate::Error> {
+    let datum_option = decode_datum(&mut output)?;
+
+    let script_ref = match output.reference_script {
+        Some(x) => Some(decode_script(x.script)?),
+        None => None,
+    };
+
+    encode_output(
+        &output.address,
+        decode_value(output.value)?,
+        datum_option,
+        script_ref,
+    )
+}
+
+/// Parses the `{hash}#{idx}` keys of the json output
+fn parse_utxo_ref(key: &str) -> Result<(Hash<32>, u64), crate::Error> {
+    let (hash, index) = key
+        .split_once('#')
+        .ok_or_else(|| crate::Error::message(format!("invalid utxo ref {}", key)))?;
+
+    let hash = Hash::<32>::from_str(hash).map_err(|x| crate::Error::message(x.to_string()))?;
+    let index = index
+        .parse()
+        .map_err(|_| crate::Error::message(format!("invalid utxo index in {}", key)))?;
+
+    Ok((hash, index))
+}
+
+/// Walks the top-level json map one entry at a time, the whole UTxO set of
+/// mainnet doesn't fit comfortably in memory
+struct JsonUtxoVisitor<'a> {
+    importer: &'a mut Importer,
+}
+
+impl<'de, 'a> Visitor<'de> for JsonUtxoVisitor<'a> {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of utxo refs to transaction outputs")
+    }
+
+    fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        let mut count = 0;
+
+        while let Some((key, output)) = access.next_entry::<String, JsonOutput>()? {
+            let cbor = encode_json_output(output)
+                .map_err(|err| de::Error::custom(format!("utxo {}: {}", key, err)))?;
+
+            let (hash, index) = parse_utxo_ref(&key).map_err(de::Error::custom)?;
+
+            self.importer
+                .insert(&hash, index, Era::Babbage, cbor)
+                .map_err(de::Error::custom)?;
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+fn import_json(reader: impl Read, importer: &mut Importer) -> Result<u64, crate::Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+
+    deserializer
+        .deserialize_map(JsonUtxoVisitor { importer })
+        .map_err(|x| crate::Error::message(x.to_string()))
+}
+
+/// Reads exactly `len` bytes, appending them to `out`
+fn read_bytes(reader: &mut impl Read, len: u64, out: &mut Vec<u8>) -> Result<(), crate::Error> {
+    let read = reader
+        .by_ref()
+        .take(len)
+        .read_to_end(out)
+        .map_err(crate::Error::cbor)?;
+
+    match read as u64 == len {
+        true => Ok(()),
+        false => Err(crate::Error::cbor("truncated CBOR snapshot")),
+    }
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8, crate::Error> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte).map_err(crate::Error::cbor)?;
+
+    Ok(byte[0])
+}
+
+/// Reads the argument of a CBOR header, `None` for indefinite lengths
+fn read_argument(
+    reader: &mut impl Read,
+    initial: u8,
+    out: &mut Vec<u8>,
+) -> Result<Option<u64>, crate::Error> {
+    let size = match initial & 0x1f {
+        x @ 0..=23 => return Ok(Some(x as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        31 => return Ok(None),
+        x => {
+            return Err(crate::Error::cbor(format!(
+                "invalid CBOR additional info {}",
+                x
+            )))
+        }
+    };
+
+    let start = out.len();
+    read_bytes(reader, size, out)?;
+
+    let value = out[start..]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+
+    Ok(Some(value))
+}
+
+/// Copies the raw bytes of the next CBOR item, whose first byte was already
+/// read, without decoding it. Only nested items are held in memory, so the
+/// snapshot can be read one entry at a time.
+fn copy_item(reader: &mut impl Read, initial: u8, out: &mut Vec<u8>) -> Result<(), crate::Error> {
+    out.push(initial);
+
+    let major = initial >> 5;
+    let argument = read_argument(reader, initial, out)?;
+
+    match (major, argument) {
+        // integers and simple values, the argument is the whole item
+        (0 | 1 | 7, Some(_)) => Ok(()),
+        (2 | 3, Some(len)) => read_bytes(reader, len, out),
+        (4, Some(len)) => copy_items(reader, len, out),
+        (5, Some(len)) => copy_items(reader, len * 2, out),
+        (6, Some(_)) => copy_items(reader, 1, out),
+        // indefinite strings, arrays and maps end with a break
+        (2..=5, None) => loop {
+            match read_byte(reader)? {
+                0xff => {
+                    out.push(0xff);
+                    break Ok(());
+                }
+                x => copy_item(reader, x, out)?,
+            }
+        },
+        _ => Err(crate::Error::cbor(format!(
+            "unexpected CBOR header {:#04x}",
+            initial
+        ))),
+    }
+}
+
+fn copy_items(reader: &mut impl Read, count: u64, out: &mut Vec<u8>) -> Result<(), crate::Error> {
+    for _ in 0..count {
+        let initial = read_byte(reader)?;
+        copy_item(reader, initial, out)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes the `[tx_hash, index]` key of a snapshot entry
+fn decode_cbor_key(key: &[u8]) -> Result<(Hash<32>, u64), crate::Error> {
+    let mut decoder = minicbor::Decoder::new(key);
+
+    decoder.array().map_err(crate::Error::cbor)?;
+    let hash: [u8; 32] = decoder
+        .bytes()
+        .map_err(crate::Error::cbor)?
+        .try_into()
+        .map_err(|_| crate::Error::cbor("invalid tx hash length"))?;
+
+    let index = decoder.u64().map_err(crate::Error::cbor)?;
+
+    Ok((Hash::new(hash), index))
+}
+
+/// Walks the top-level CBOR map one entry at a time, minicbor can only decode
+/// from a slice so each entry is copied out of the reader first
+fn import_cbor(mut reader: impl Read, importer: &mut Importer) -> Result<u64, crate::Error> {
+    let initial = read_byte(&mut reader)?;
+
+    if initial >> 5 != 5 {
+        return Err(crate::Error::cbor("CBOR snapshot is not a map"));
+    }
+
+    let len = read_argument(&mut reader, initial, &mut vec![])?;
+
+    let mut count = 0;
+    let mut key = vec![];
+    let mut cbor = vec![];
+
+    loop {
+        let initial = match len {
+            Some(len) if count >= len => break,
+            Some(_) => read_byte(&mut reader)?,
+            None => match read_byte(&mut reader)? {
+                0xff => break,
+                x => x,
+            },
+        };
+
+        key.clear();
+        copy_item(&mut reader, initial, &mut key)?;
+        let (hash, index) = decode_cbor_key(&key)?;
+
+        // keep the output as-is, babbage decoding handles legacy outputs too
+        cbor.clear();
+        let initial = read_byte(&mut reader)?;
+        copy_item(&mut reader, initial, &mut cbor)?;
+
+        importer.insert(&hash, index, Era::Babbage, cbor.clone())?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enrich::kv;
+
+    /// Enterprise mainnet address with a key hash of `0x11` bytes
+    const ADDRESS: &str = "addr1vyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygatvcjl";
+
+    fn importer(name: &str) -> Importer {
+        let path =
+            std::env::temp_dir().join(format!("scrolls-snapshot-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&path);
+
+        Importer::open(kv::Engine::Sled, &path.to_string_lossy()).unwrap()
+    }
+
+    fn hash(byte: u8) -> Hash<32> {
+        Hash::new([byte; 32])
+    }
+
+    #[test]
+    fn imports_cli_json() {
+        let policy = Hash::<28>::new([0x22; 28]);
+
+        let json = serde_json::json!({
+            format!("{}#0", hash(0xaa)): {
+                "address": ADDRESS,
+                "value": { "lovelace": 1000000 },
+                "datumhash": null,
+                "referenceScript": null
+            },
+            format!("{}#3", hash(0xaa)): {
+                "address": ADDRESS,
+                "value": { "lovelace": 2000000, policy.to_string(): { "4e4654": 1 } },
+                "datumhash": hash(0xdd).to_string()
+            }
+        });
+
+        let mut importer = importer("json");
+        let count = import_json(json.to_string().as_bytes(), &mut importer).unwrap();
+        assert_eq!(count, 2);
+
+        let plain = encode_output(ADDRESS, Value::Coin(1000000), None, None).unwrap();
+        assert_eq!(
+            importer.find_utxo(&hash(0xaa), 0).unwrap(),
+            Some((Era::Babbage, plain))
+        );
+
+        let with_assets = encode_output(
+            ADDRESS,
+            build_value(2000000, vec![(policy, b"NFT".to_vec(), 1)]),
+            Some(DatumOption::Hash(hash(0xdd))),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            importer.find_utxo(&hash(0xaa), 3).unwrap(),
+            Some((Era::Babbage, with_assets))
+        );
+
+        assert_eq!(importer.find_utxo(&hash(0xaa), 1).unwrap(), None);
+    }
+
+    /// Entries of a CBOR snapshot, without the map header
+    fn cbor_entries() -> (Vec<u8>, Vec<(Hash<32>, u64, Vec<u8>)>) {
+        // a legacy output as an array of address and coin
+        let address = Bytes::from(decode_address(ADDRESS).unwrap());
+        let legacy = minicbor::to_vec((address, 5000000u64)).unwrap();
+
+        // a babbage output with nested maps and a tagged inline datum
+        let babbage = encode_output(
+            ADDRESS,
+            build_value(3000000, vec![(Hash::new([0x22; 28]), b"FT".to_vec(), 7)]),
+            Some(DatumOption::Data(CborWrap(PlutusData::BoundedBytes(
+                vec![0xca, 0xfe].into(),
+            )))),
+            None,
+        )
+        .unwrap();
+
+        let expected = vec![(hash(0xbb), 1, legacy), (hash(0xcc), 0, babbage)];
+
+        let mut bytes = vec![];
+
+        for (hash, index, output) in expected.iter() {
+            let key = (Bytes::from(hash.to_vec()), *index);
+
+            bytes.extend(minicbor::to_vec(key).unwrap());
+            bytes.extend(output);
+        }
+
+        (bytes, expected)
+    }
+
+    #[test]
+    fn imports_cbor_map() {
+        let (entries, expected) = cbor_entries();
+
+        let definite = [vec![0xa2], entries.clone()].concat();
+        let indefinite = [vec![0xbf], entries, vec![0xff]].concat();
+
+        for (name, snapshot) in [("cbor-def", definite), ("cbor-indef", indefinite)] {
+            let mut importer = importer(name);
+            let count = import_cbor(snapshot.as_slice(), &mut importer).unwrap();
+            assert_eq!(count, 2);
+
+            for (hash, index, output) in expected.iter() {
+                assert_eq!(
+                    importer.find_utxo(hash, *index).unwrap(),
+                    Some((Era::Babbage, output.clone()))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_cbor() {
+        let (entries, _) = cbor_entries();
+        let snapshot = [vec![0xa2], entries].concat();
+
+        let mut importer = importer("cbor-truncated");
+        let truncated = &snapshot[..snapshot.len() - 1];
+        assert!(import_cbor(truncated, &mut importer).is_err());
+    }
+}
@@ -2,7 +2,17 @@ use pallas::ledger::addresses::ShelleyAddress;
 use std::{collections::HashMap, fmt::Debug};
 
 use pallas::{
-    ledger::traverse::{Era, MultiEraBlock, MultiEraOutput, MultiEraTx, OutputRef},
+    codec::{minicbor, utils::CborWrap},
+    crypto::hash::Hash,
+    ledger::{
+        primitives::{
+            alonzo::PlutusData,
+            babbage::{self, DatumOption, Script},
+        },
+        traverse::{
+            ComputeHash, Era, MultiEraBlock, MultiEraInput, MultiEraOutput, MultiEraTx, OutputRef,
+        },
+    },
     network::miniprotocols::Point,
 };
 
@@ -31,9 +41,36 @@ impl RawBlockPayload {
 #[derive(Default, Debug, Clone)]
 pub struct BlockContext {
     utxos: HashMap<String, (Era, Vec<u8>)>,
+    datums: HashMap<Hash<32>, Vec<u8>>,
+    witness_datums: HashMap<Hash<32>, PlutusData>,
 }
 
 impl BlockContext {
+    pub fn import_datum(&mut self, hash: Hash<32>, cbor: Vec<u8>) {
+        self.datums.insert(hash, cbor);
+    }
+
+    /// Indexes the datums published in the witness sets of the block, so that
+    /// they can be found even if the enrich stage doesn't keep datums
+    pub fn import_witness_datums(&mut self, block: &MultiEraBlock) {
+        self.witness_datums.extend(witness_datums(block));
+    }
+
+    /// Resolves a datum by its hash, regardless of the block where it was
+    /// first published
+    pub fn find_datum(&self, hash: &Hash<32>) -> Result<PlutusData, Error> {
+        if let Some(datum) = self.witness_datums.get(hash) {
+            return Ok(datum.clone());
+        }
+
+        let cbor = self
+            .datums
+            .get(hash)
+            .ok_or_else(|| Error::missing_datum(hash))?;
+
+        minicbor::decode(cbor).map_err(crate::Error::cbor)
+    }
+
     pub fn import_ref_output(&mut self, key: &OutputRef, era: Era, cbor: Vec<u8>) {
         self.utxos.insert(key.to_string(), (era, cbor));
     }
@@ -51,13 +88,12 @@ impl BlockContext {
         self.utxos.keys().map(|x| x.clone()).collect()
     }
 
-    pub fn find_consumed_txos(
+    fn find_txos(
         &self,
-        tx: &MultiEraTx,
+        inputs: &[MultiEraInput],
         policy: &RuntimePolicy,
     ) -> Result<Vec<(OutputRef, MultiEraOutput)>, Error> {
-        let items = tx
-            .consumes()
+        let items = inputs
             .iter()
             .map(|i| i.output_ref())
             .map(|r| self.find_utxo(&r).map(|u| (r, u)))
@@ -69,6 +105,155 @@ impl BlockContext {
 
         Ok(items)
     }
+
+    pub fn find_consumed_txos(
+        &self,
+        tx: &MultiEraTx,
+        policy: &RuntimePolicy,
+    ) -> Result<Vec<(OutputRef, MultiEraOutput)>, Error> {
+        self.find_txos(&tx.consumes(), policy)
+    }
+
+    /// UTxOs read by the tx through reference inputs (Babbage onwards)
+    pub fn find_reference_txos(
+        &self,
+        tx: &MultiEraTx,
+        policy: &RuntimePolicy,
+    ) -> Result<Vec<(OutputRef, MultiEraOutput)>, Error> {
+        self.find_txos(&tx.reference_inputs(), policy)
+    }
+
+    /// UTxOs put up as collateral by the tx, only consumed if the tx fails
+    /// phase-2 validation
+    pub fn find_collateral_txos(
+        &self,
+        tx: &MultiEraTx,
+        policy: &RuntimePolicy,
+    ) -> Result<Vec<(OutputRef, MultiEraOutput)>, Error> {
+        self.find_txos(&tx.collateral(), policy)
+    }
+
+    /// Inline datums held by the UTxOs referenced by the tx
+    pub fn find_reference_datums(
+        &self,
+        tx: &MultiEraTx,
+        policy: &RuntimePolicy,
+    ) -> Result<Vec<(OutputRef, PlutusData)>, Error> {
+        let items = self
+            .find_reference_txos(tx, policy)?
+            .into_iter()
+            .filter_map(|(r, u)| inline_datum(&u).map(|d| (r, d)))
+            .collect();
+
+        Ok(items)
+    }
+
+    /// Scripts held by the UTxOs referenced by the tx
+    pub fn find_reference_scripts(
+        &self,
+        tx: &MultiEraTx,
+        policy: &RuntimePolicy,
+    ) -> Result<Vec<(OutputRef, Script)>, Error> {
+        let items = self
+            .find_reference_txos(tx, policy)?
+            .into_iter()
+            .filter_map(|(r, u)| reference_script(&u).map(|s| (r, s)))
+            .collect();
+
+        Ok(items)
+    }
+}
+
+/// Every UTxO that needs to be resolved to fully evaluate the tx: regular
+/// inputs, collateral inputs and reference inputs
+pub fn required_txos(tx: &MultiEraTx) -> Vec<OutputRef> {
+    let mut refs: Vec<_> = tx
+        .inputs()
+        .iter()
+        .chain(tx.collateral().iter())
+        .chain(tx.reference_inputs().iter())
+        .map(|i| i.output_ref())
+        .collect();
+
+    refs.sort_by_key(|r| r.to_string());
+    refs.dedup_by_key(|r| r.to_string());
+
+    refs
+}
+
+/// Datums published in the witness sets of the block, keyed by their hash
+pub fn witness_datums(block: &MultiEraBlock) -> HashMap<Hash<32>, PlutusData> {
+    let witnesses: Vec<PlutusData> = match &block {
+        MultiEraBlock::Babbage(babbage) => babbage
+            .transaction_witness_sets
+            .iter()
+            .map(|w| w.clone().unwrap())
+            .flat_map(|w| w.plutus_data)
+            .flat_map(|w| w)
+            .map(|w| w.unwrap())
+            .collect(),
+        MultiEraBlock::AlonzoCompatible(alonzo, _) => alonzo
+            .transaction_witness_sets
+            .iter()
+            .map(|w| w.clone().unwrap())
+            .flat_map(|w| w.plutus_data)
+            .flat_map(|w| w)
+            .map(|w| w.unwrap())
+            .collect(),
+        _ => vec![],
+    };
+
+    witnesses
+        .into_iter()
+        .map(|datum| (datum.compute_hash(), datum))
+        .collect()
+}
+
+/// Hash of the datum attached to the output, either by hash or inline
+pub fn datum_hash(output: &MultiEraOutput) -> Option<Hash<32>> {
+    match output.datum() {
+        Some(DatumOption::Hash(hash)) => Some(hash),
+        Some(DatumOption::Data(CborWrap(datum))) => Some(datum.compute_hash()),
+        None => None,
+    }
+}
+
+/// Datum of the output, either inline or by hash through the context of the
+/// block
+pub fn resolve_datum(
+    ctx: &BlockContext,
+    output: &MultiEraOutput,
+) -> Result<Option<PlutusData>, Error> {
+    match output.datum() {
+        Some(DatumOption::Data(CborWrap(datum))) => Ok(Some(datum)),
+        Some(DatumOption::Hash(hash)) => ctx.find_datum(&hash).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Datum embedded in the output itself, if any
+pub fn inline_datum(output: &MultiEraOutput) -> Option<PlutusData> {
+    match output.datum() {
+        Some(DatumOption::Data(CborWrap(datum))) => Some(datum),
+        _ => None,
+    }
+}
+
+/// Script carried by the output to be used through reference inputs, if any
+pub fn reference_script(output: &MultiEraOutput) -> Option<Script> {
+    match output {
+        MultiEraOutput::Babbage(x) => {
+            let output: &babbage::TransactionOutput = x;
+
+            match output {
+                babbage::TransactionOutput::PostAlonzo(x) => {
+                    x.script_ref.clone().map(|CborWrap(script)| script)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -96,6 +281,8 @@ pub type Member = String;
 pub type PolicyId = String;
 pub type TokenName = String;
 pub type Timestamp = u64;
+pub type Key = String;
+pub type Delta = i64;
 
 #[derive(Clone, Debug)]
 pub enum Value {
@@ -141,6 +328,11 @@ pub enum CRDTCommand {
         tx_idx: usize,
         point: Point,
     },
+    SetAdd(Set, Member, Point),
+    SetRemove(Set, Member, Point),
+    PNCounter(Key, Delta, Point),
+    AnyWriteWins(Key, Value, Point),
+    KeyRemove(Key, Point),
     BlockFinished(Point),
     RollBack(Point),
 }
@@ -1,6 +1,6 @@
 use hex::ToHex;
 use pallas::crypto::hash::Hash;
-use pallas::ledger::addresses::Address;
+use pallas::ledger::addresses::{Address, ShelleyDelegationPart};
 use pallas::ledger::traverse::MultiEraOutput;
 use pallas::ledger::traverse::{Asset, MultiEraBlock, OutputRef};
 use pallas::network::miniprotocols::Point;
@@ -9,9 +9,31 @@ use serde::Deserialize;
 use crate::model::CRDTCommand;
 use crate::{crosscut, model, prelude::*};
 
+/// Pseudo-policy used to track lovelace along with native assets
+pub const ADA_POLICY: &str = "lovelace";
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateBy {
+    /// one entry per UTxO and asset, tagged with its full address
+    Address,
+    /// running balance per stake credential and asset, outputs of addresses
+    /// without a stake credential are ignored
+    StakeCredential,
+}
+
+impl Default for AggregateBy {
+    fn default() -> Self {
+        AggregateBy::Address
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Config {
-    // TODO: save_ada: bool // default to false
+    /// Also track lovelace, as the `lovelace` pseudo-policy with an empty
+    /// token name
+    pub save_ada: Option<bool>,
+    pub aggregate_by: Option<AggregateBy>,
     pub filter: Option<crosscut::filters::Predicate>,
 }
 
@@ -21,13 +43,133 @@ pub struct Reducer {
 }
 
 impl Reducer {
+    /// Amounts held by the output as (policy, name, amount), in hex
+    fn output_assets(&self, tx_output: &MultiEraOutput) -> Vec<(String, String, u64)> {
+        let mut assets: Vec<_> = tx_output
+            .non_ada_assets()
+            .iter()
+            .filter_map(|asset| match asset {
+                Asset::Ada(_) => None,
+                Asset::NativeAsset(cs, tn, amount) => {
+                    Some((cs.encode_hex(), tn.encode_hex(), *amount))
+                }
+            })
+            .collect();
+
+        let lovelace = tx_output.lovelace_amount();
+
+        if self.config.save_ada.unwrap_or(false) && lovelace > 0 {
+            assets.push((ADA_POLICY.to_string(), "".to_string(), lovelace));
+        }
+
+        assets
+    }
+
+    /// Hex of the stake credential of the output address, if it has one
+    fn stake_credential(tx_output: &MultiEraOutput) -> Result<Option<String>, crate::Error> {
+        let address = match tx_output.address().map_err(crate::Error::ledger)? {
+            Address::Shelley(x) => x,
+            _ => return Ok(None),
+        };
+
+        match address.delegation() {
+            ShelleyDelegationPart::Key(x) => Ok(Some(x.encode_hex())),
+            ShelleyDelegationPart::Script(x) => Ok(Some(x.encode_hex())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Counter deltas of the assets of the output under its stake credential,
+    /// `sign` is -1 for spent outputs
+    fn stake_commands(
+        &self,
+        tx_output: &MultiEraOutput,
+        sign: i64,
+        point: &Point,
+    ) -> Result<Vec<CRDTCommand>, crate::Error> {
+        let stake = match Self::stake_credential(tx_output)? {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+
+        let commands = self
+            .output_assets(tx_output)
+            .into_iter()
+            .map(|(policy, name, amount)| {
+                let key = match name.as_str() {
+                    "" => format!("{}.{}", stake, policy),
+                    _ => format!("{}.{}.{}", stake, policy, name),
+                };
+
+                // quantities above i64::MAX are only possible for native assets
+                // with absurd supplies, counters saturate instead of wrapping
+                let delta = i64::try_from(amount).unwrap_or(i64::MAX) * sign;
+
+                CRDTCommand::PNCounter(key, delta, point.clone())
+            })
+            .collect();
+
+        Ok(commands)
+    }
+
+    fn produced_commands(
+        &self,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+        utxo_idx: usize,
+        tx_hash: Hash<32>,
+    ) -> Result<Vec<CRDTCommand>, crate::Error> {
+        if self.config.aggregate_by.unwrap_or_default() == AggregateBy::StakeCredential {
+            return self.stake_commands(tx_output, 1, point);
+        }
+
+        let address = match tx_output.address().map_err(crate::Error::ledger)? {
+            Address::Shelley(x) => x,
+            _ => return Ok(vec![]),
+        };
+
+        let commands = self
+            .output_assets(tx_output)
+            .into_iter()
+            .map(|(policy, name, amount)| CRDTCommand::VotingPowerCreated {
+                owner: address.clone(),
+                policy,
+                name,
+                amount,
+                point: point.clone(),
+                tx_id: tx_hash.encode_hex(),
+                tx_idx: utxo_idx,
+            })
+            .collect();
+
+        Ok(commands)
+    }
+
     fn process_consumed_txo(
         &mut self,
         block: &MultiEraBlock,
+        ctx: &model::BlockContext,
         input: &OutputRef,
         output: &mut super::OutputPort,
     ) -> Result<(), gasket::error::Error> {
         let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        if self.config.aggregate_by.unwrap_or_default() == AggregateBy::StakeCredential {
+            // the spent output is needed to know what to subtract and from whom
+            let utxo = ctx.find_utxo(input).apply_policy(&self.policy).or_panic()?;
+
+            let commands = match utxo {
+                Some(utxo) => self.stake_commands(&utxo, -1, &point).or_panic()?,
+                None => vec![],
+            };
+
+            for crdt in commands {
+                output.send(gasket::messaging::Message::from(crdt))?;
+            }
+
+            return Ok(());
+        }
+
         output.send(gasket::messaging::Message::from(
             CRDTCommand::VotingPowerSpent {
                 tx_id: input.hash().encode_hex(),
@@ -46,31 +188,15 @@ impl Reducer {
         tx_hash: Hash<32>,
     ) -> Result<(), gasket::error::Error> {
         let point = Point::Specific(block.slot(), block.hash().to_vec());
-        let address = match tx_output.address().or_panic()? {
-            Address::Shelley(x) => x,
-            _ => return Ok(()),
-        };
 
-        tx_output.non_ada_assets().iter().for_each(|asset| {
-            let (policy, name, amount) = match &asset {
-                Asset::Ada(_) => unreachable!(),
-                Asset::NativeAsset(cs, tn, amount) => (cs.encode_hex(), tn.encode_hex(), *amount),
-            };
+        let commands = self
+            .produced_commands(tx_output, &point, utxo_idx, tx_hash)
+            .or_panic()?;
+
+        for crdt in commands {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
 
-            output
-                .send(gasket::messaging::Message::from(
-                    CRDTCommand::VotingPowerCreated {
-                        owner: address.clone(),
-                        policy,
-                        name,
-                        amount,
-                        point: point.clone(),
-                        tx_id: tx_hash.encode_hex(),
-                        tx_idx: utxo_idx,
-                    },
-                ))
-                .unwrap()
-        });
         Ok(())
     }
 
@@ -83,7 +209,7 @@ impl Reducer {
         for tx in block.txs().into_iter() {
             if filter_matches!(self, block, &tx, ctx) {
                 for consumed in tx.consumes().iter().map(|i| i.output_ref()) {
-                    self.process_consumed_txo(block, &consumed, output)?;
+                    self.process_consumed_txo(block, ctx, &consumed, output)?;
                 }
 
                 for (idx, produced) in tx.produces() {
@@ -106,3 +232,125 @@ impl Config {
         super::Reducer::BalanceByAddress(reducer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reducers::fixtures::{
+        babbage_output, decode_output, BASE_ADDRESS, ENTERPRISE_ADDRESS,
+    };
+
+    fn reducer(aggregate_by: AggregateBy) -> Reducer {
+        Reducer {
+            config: Config {
+                save_ada: Some(true),
+                aggregate_by: Some(aggregate_by),
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    fn point() -> Point {
+        Point::Specific(100, vec![0xff; 32])
+    }
+
+    fn output_cbor(address: &str) -> Vec<u8> {
+        let policy = Hash::<28>::new([0x33; 28]);
+        babbage_output(address, 2000000, &[(policy, b"TOKEN", 5)], None)
+    }
+
+    fn counters(commands: Vec<CRDTCommand>) -> Vec<(String, i64)> {
+        commands
+            .into_iter()
+            .map(|crdt| match crdt {
+                CRDTCommand::PNCounter(key, delta, _) => (key, delta),
+                other => panic!("unexpected command {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aggregates_by_stake_credential() {
+        let cbor = output_cbor(BASE_ADDRESS);
+        let output = decode_output(&cbor);
+
+        let reducer = reducer(AggregateBy::StakeCredential);
+        let stake = hex::encode([0x22; 28]);
+        let asset = format!(
+            "{}.{}.{}",
+            stake,
+            hex::encode([0x33; 28]),
+            hex::encode("TOKEN")
+        );
+
+        let produced = reducer
+            .produced_commands(&output, &point(), 0, Hash::new([0xaa; 32]))
+            .unwrap();
+
+        // lovelace is tracked under the pseudo-policy, without token name
+        assert_eq!(
+            counters(produced),
+            vec![(asset.clone(), 5), (format!("{}.lovelace", stake), 2000000)]
+        );
+
+        let consumed = reducer.stake_commands(&output, -1, &point()).unwrap();
+
+        assert_eq!(
+            counters(consumed),
+            vec![(asset, -5), (format!("{}.lovelace", stake), -2000000)]
+        );
+    }
+
+    #[test]
+    fn ignores_outputs_without_stake_credential() {
+        let cbor = output_cbor(ENTERPRISE_ADDRESS);
+        let output = decode_output(&cbor);
+
+        let commands = reducer(AggregateBy::StakeCredential)
+            .produced_commands(&output, &point(), 0, Hash::new([0xaa; 32]))
+            .unwrap();
+
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn aggregates_by_address() {
+        let cbor = output_cbor(BASE_ADDRESS);
+        let output = decode_output(&cbor);
+
+        let tx_hash = Hash::new([0xaa; 32]);
+
+        let commands = reducer(AggregateBy::Address)
+            .produced_commands(&output, &point(), 3, tx_hash)
+            .unwrap();
+
+        let created: Vec<_> = commands
+            .into_iter()
+            .map(|crdt| match crdt {
+                CRDTCommand::VotingPowerCreated {
+                    owner,
+                    policy,
+                    name,
+                    amount,
+                    tx_id,
+                    tx_idx,
+                    ..
+                } => {
+                    assert_eq!(Address::Shelley(owner).to_string(), BASE_ADDRESS);
+                    assert_eq!((tx_id, tx_idx), (tx_hash.to_string(), 3));
+                    (policy, name, amount)
+                }
+                other => panic!("unexpected command {:?}", other),
+            })
+            .collect();
+
+        assert_eq!(
+            created,
+            vec![
+                (hex::encode([0x33; 28]), hex::encode("TOKEN"), 5),
+                (ADA_POLICY.to_string(), "".to_string(), 2000000),
+            ]
+        );
+    }
+}
@@ -1,31 +1,41 @@
 use hex::ToHex;
-use pallas::codec::utils::CborWrap;
 use pallas::crypto::hash::Hash;
 use pallas::ledger::addresses::{
-    Network, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart,
+    Network, Pointer, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart,
 };
-use pallas::ledger::primitives::alonzo::{Constr, PlutusData};
-use pallas::ledger::primitives::babbage::DatumOption;
-use pallas::ledger::traverse::ComputeHash;
+use pallas::ledger::primitives::alonzo::{BigInt, Constr, PlutusData};
 use pallas::ledger::traverse::MultiEraOutput;
 use pallas::ledger::traverse::{Asset, MultiEraBlock, OutputRef};
 use pallas::network::miniprotocols::Point;
 use serde::Deserialize;
-use std::collections::HashMap;
 use std::ops::Deref;
 
+use crate::crosscut::plutus::constr_index;
 use crate::{crosscut, model, prelude::*};
 
+/// One step of the path from the root of the datum to the owner address
+#[derive(Deserialize, Clone)]
+pub struct DatumPathStep {
+    /// Expected constructor index of the value at this step, any if absent
+    pub constructor: Option<u64>,
+    /// Index of the field to descend into
+    pub field: usize,
+}
+
 #[derive(Deserialize)]
 pub struct Config {
     pub key_prefix: String,
     pub filter: Option<crosscut::filters::Predicate>,
     pub policy_id_hex: Option<String>,
     pub script_address: String,
+    /// Where to find the owner (a Plutus `Address`) within the datum, defaults
+    /// to the second field of the root constructor
+    pub owner_path: Option<Vec<DatumPathStep>>,
 }
 
 pub struct Reducer {
     config: Config,
+    network: Network,
     policy: crosscut::policies::RuntimePolicy,
 }
 
@@ -50,39 +60,29 @@ impl Reducer {
         }
     }
 
-    // VERY naive
-    fn datum_to_address(datum: &PlutusData) -> Option<ShelleyAddress> {
-        let fields = get_constr(datum)?;
-        // TODO: Check constr tags
-
-        let addr_field = fields.fields.get(1)?;
-        let addr_constr = get_constr(addr_field)?;
-
-        // Spending
-
-        let spending_field = addr_constr.fields.get(0)?;
-        let spending_constr = get_constr(spending_field)?;
-
-        let spending_bytes = get_bytes(spending_constr)?;
-        let spending_part = mk_pub_key_spend_part(spending_bytes.clone())?;
+    fn datum_to_address(&self, datum: &PlutusData) -> Option<ShelleyAddress> {
+        let default_path = [DatumPathStep {
+            constructor: None,
+            field: 1,
+        }];
 
-        // Staking
+        let path = self.config.owner_path.as_deref().unwrap_or(&default_path);
 
-        let maybe_staking_field = addr_constr.fields.get(1)?;
-        let maybe_staking_constr = get_constr(maybe_staking_field)?;
+        let mut current = datum;
 
-        let staking_cred_field = maybe_staking_constr.fields.get(0)?;
-        let staking_cred_constr = get_constr(staking_cred_field)?;
+        for step in path {
+            let constr = get_constr(current)?;
 
-        let staking_field = staking_cred_constr.fields.get(0)?;
-        let staking_constr = get_constr(staking_field)?;
+            if let Some(expected) = step.constructor {
+                if constr_index(constr)? != expected {
+                    return None;
+                }
+            }
 
-        let staking_bytes = get_bytes(staking_constr)?;
-        let stakeing_part = mk_pub_key_stake_part(staking_bytes.clone())?;
+            current = constr.fields.get(step.field)?;
+        }
 
-        // TODO: Don't hardcode network id
-        let addr = ShelleyAddress::new(Network::Mainnet, spending_part, stakeing_part);
-        Some(addr)
+        plutus_to_address(current, self.network)
     }
 
     fn process_consumed_txo(
@@ -104,6 +104,7 @@ impl Reducer {
     fn process_produced_txo(
         &mut self,
         block: &MultiEraBlock,
+        ctx: &model::BlockContext,
         tx_output: &MultiEraOutput,
         output: &mut super::OutputPort,
         utxo_idx: usize,
@@ -119,18 +120,11 @@ impl Reducer {
             return Ok(());
         }
 
-        let owner = match tx_output.datum() {
-            Some(DatumOption::Data(CborWrap(datum))) => Self::datum_to_address(&datum),
-            Some(DatumOption::Hash(hash)) => {
-                let datums = get_datums(block);
-                let datum = datums.get(&hash);
-                match datum {
-                    Some(datum) => Self::datum_to_address(datum),
-                    None => None,
-                }
-            }
-            None => None,
-        };
+        let datum = model::resolve_datum(ctx, tx_output)
+            .apply_policy(&self.policy)
+            .or_panic()?;
+
+        let owner = datum.flatten().and_then(|x| self.datum_to_address(&x));
         log::warn!("Found Genius stake owner: {:?}", owner);
         match owner {
             Some(owner) => {
@@ -168,7 +162,7 @@ impl Reducer {
                 }
 
                 for (idx, produced) in tx.produces() {
-                    self.process_produced_txo(&block, &produced, output, idx, tx.hash())?;
+                    self.process_produced_txo(&block, ctx, &produced, output, idx, tx.hash())?;
                 }
             }
         }
@@ -178,9 +172,14 @@ impl Reducer {
 }
 
 impl Config {
-    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+    pub fn plugin(
+        self,
+        chain: &crosscut::ChainWellKnownInfo,
+        policy: &crosscut::policies::RuntimePolicy,
+    ) -> super::Reducer {
         let reducer = Reducer {
             config: self,
+            network: Network::from(chain.address_network_id),
             policy: policy.clone(),
         };
 
@@ -188,58 +187,263 @@ impl Config {
     }
 }
 
-fn get_datums(block: &MultiEraBlock) -> HashMap<Hash<32>, PlutusData> {
-    let witnesses: Vec<PlutusData> = match &block {
-        MultiEraBlock::Babbage(babbage) => babbage
-            .transaction_witness_sets
-            .iter()
-            .map(|w| w.clone().unwrap())
-            .flat_map(|w| w.plutus_data)
-            .flat_map(|w| w)
-            .map(|w| w.unwrap())
-            .collect(),
-        MultiEraBlock::AlonzoCompatible(alonzo, _) => alonzo
-            .transaction_witness_sets
-            .iter()
-            .map(|w| w.clone().unwrap())
-            .flat_map(|w| w.plutus_data)
-            .flat_map(|w| w)
-            .map(|w| w.unwrap())
-            .collect(),
-        _ => vec![],
-    };
-    let mut datums = HashMap::new();
-    for witness in witnesses {
-        let hash = witness.compute_hash();
-        datums.insert(hash, witness);
+fn get_constr(data: &PlutusData) -> Option<&Constr<PlutusData>> {
+    match data {
+        PlutusData::Constr(c) => Some(c),
+        _ => None,
     }
-    datums
 }
 
-fn get_constr(data: &PlutusData) -> Option<&Constr<PlutusData>> {
+/// Returns the fields of a constr with the expected constructor index
+fn get_fields(data: &PlutusData, index: u64) -> Option<&Vec<PlutusData>> {
+    let constr = get_constr(data)?;
+
+    match constr_index(constr)? == index {
+        true => Some(&constr.fields),
+        false => None,
+    }
+}
+
+fn get_bytes(data: &PlutusData) -> Option<Hash<28>> {
     match data {
-        PlutusData::Constr(c) => Some(c),
+        PlutusData::BoundedBytes(b) => {
+            let arr: [u8; 28] = b.deref().clone().try_into().ok()?;
+            Some(Hash::new(arr))
+        }
         _ => None,
     }
 }
 
-fn get_bytes(data: &Constr<PlutusData>) -> Option<&Vec<u8>> {
-    match &data.fields[0] {
-        PlutusData::BoundedBytes(b) => Some(b.deref()),
+fn get_u64(data: &PlutusData) -> Option<u64> {
+    match data {
+        PlutusData::BigInt(BigInt::Int(x)) => {
+            let value: i128 = (*x).into();
+            u64::try_from(value).ok()
+        }
         _ => None,
     }
 }
 
-fn mk_pub_key_spend_part(bytes: Vec<u8>) -> Option<ShelleyPaymentPart> {
-    let arr: [u8; 28] = bytes.try_into().ok()?;
-    let hash: Hash<28> = Hash::new(arr);
-    let payment_part = ShelleyPaymentPart::key_hash(hash);
-    Some(payment_part)
+/// Decodes a Plutus `Credential` into (is_script, hash)
+fn plutus_to_credential(data: &PlutusData) -> Option<(bool, Hash<28>)> {
+    if let Some(fields) = get_fields(data, 0) {
+        return Some((false, get_bytes(fields.get(0)?)?));
+    }
+
+    let fields = get_fields(data, 1)?;
+    Some((true, get_bytes(fields.get(0)?)?))
 }
 
-fn mk_pub_key_stake_part(bytes: Vec<u8>) -> Option<ShelleyDelegationPart> {
-    let arr: [u8; 28] = bytes.try_into().ok()?;
-    let hash: Hash<28> = Hash::new(arr);
-    let stake_part = ShelleyDelegationPart::Key(hash);
-    Some(stake_part)
+/// Decodes a Plutus `Maybe StakingCredential`
+fn plutus_to_delegation(data: &PlutusData) -> Option<ShelleyDelegationPart> {
+    // Nothing
+    if get_fields(data, 1).is_some() {
+        return Some(ShelleyDelegationPart::Null);
+    }
+
+    let staking = get_fields(data, 0)?.get(0)?;
+
+    // StakingHash
+    if let Some(fields) = get_fields(staking, 0) {
+        let part = match plutus_to_credential(fields.get(0)?)? {
+            (false, hash) => ShelleyDelegationPart::Key(hash),
+            (true, hash) => ShelleyDelegationPart::Script(hash),
+        };
+
+        return Some(part);
+    }
+
+    // StakingPtr
+    let fields = get_fields(staking, 1)?;
+
+    let pointer = Pointer::new(
+        get_u64(fields.get(0)?)?,
+        get_u64(fields.get(1)?)?,
+        get_u64(fields.get(2)?)?,
+    );
+
+    Some(ShelleyDelegationPart::Pointer(pointer))
+}
+
+/// Decodes a Plutus `Address` into a Shelley address of the given network
+fn plutus_to_address(data: &PlutusData, network: Network) -> Option<ShelleyAddress> {
+    let fields = get_fields(data, 0)?;
+
+    let payment = match plutus_to_credential(fields.get(0)?)? {
+        (false, hash) => ShelleyPaymentPart::key_hash(hash),
+        (true, hash) => ShelleyPaymentPart::script_hash(hash),
+    };
+
+    let delegation = plutus_to_delegation(fields.get(1)?)?;
+
+    Some(ShelleyAddress::new(network, payment, delegation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constr(index: u64, fields: Vec<PlutusData>) -> PlutusData {
+        PlutusData::Constr(Constr {
+            tag: 121 + index,
+            any_constructor: None,
+            fields: fields.into(),
+        })
+    }
+
+    fn bytes(value: &[u8]) -> PlutusData {
+        PlutusData::BoundedBytes(value.to_vec().into())
+    }
+
+    fn int(value: u64) -> PlutusData {
+        PlutusData::BigInt(BigInt::Int(value.into()))
+    }
+
+    fn pubkey_credential(byte: u8) -> PlutusData {
+        constr(0, vec![bytes(&[byte; 28])])
+    }
+
+    fn script_credential(byte: u8) -> PlutusData {
+        constr(1, vec![bytes(&[byte; 28])])
+    }
+
+    /// `Some (StakingHash credential)`
+    fn staking_hash(credential: PlutusData) -> PlutusData {
+        constr(0, vec![constr(0, vec![credential])])
+    }
+
+    fn nothing() -> PlutusData {
+        constr(1, vec![])
+    }
+
+    fn reducer(owner_path: Option<Vec<DatumPathStep>>) -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: "genius".to_string(),
+                filter: None,
+                policy_id_hex: None,
+                script_address: "addr1".to_string(),
+                owner_path,
+            },
+            network: Network::Mainnet,
+            policy: Default::default(),
+        }
+    }
+
+    #[test]
+    fn decodes_credentials() {
+        let hash = Hash::new([0x11; 28]);
+
+        assert_eq!(
+            plutus_to_credential(&pubkey_credential(0x11)),
+            Some((false, hash))
+        );
+        assert_eq!(
+            plutus_to_credential(&script_credential(0x11)),
+            Some((true, hash))
+        );
+
+        // hashes must be 28 bytes long
+        assert_eq!(plutus_to_credential(&constr(0, vec![bytes(&[1, 2])])), None);
+        assert_eq!(
+            plutus_to_credential(&constr(2, vec![bytes(&[0x11; 28])])),
+            None
+        );
+    }
+
+    #[test]
+    fn decodes_delegation() {
+        let hash = Hash::new([0x22; 28]);
+
+        assert_eq!(
+            plutus_to_delegation(&nothing()),
+            Some(ShelleyDelegationPart::Null)
+        );
+        assert_eq!(
+            plutus_to_delegation(&staking_hash(pubkey_credential(0x22))),
+            Some(ShelleyDelegationPart::Key(hash))
+        );
+        assert_eq!(
+            plutus_to_delegation(&staking_hash(script_credential(0x22))),
+            Some(ShelleyDelegationPart::Script(hash))
+        );
+
+        // `Some (StakingPtr slot tx_idx cert_idx)`
+        let pointer = constr(0, vec![constr(1, vec![int(100), int(2), int(3)])]);
+        assert_eq!(
+            plutus_to_delegation(&pointer),
+            Some(ShelleyDelegationPart::Pointer(Pointer::new(100, 2, 3)))
+        );
+
+        let incomplete = constr(0, vec![constr(1, vec![int(100), int(2)])]);
+        assert_eq!(plutus_to_delegation(&incomplete), None);
+    }
+
+    #[test]
+    fn decodes_address() {
+        let data = constr(
+            0,
+            vec![
+                script_credential(0x11),
+                staking_hash(pubkey_credential(0x22)),
+            ],
+        );
+
+        let expected = ShelleyAddress::new(
+            Network::Testnet,
+            ShelleyPaymentPart::script_hash(Hash::new([0x11; 28])),
+            ShelleyDelegationPart::Key(Hash::new([0x22; 28])),
+        );
+
+        assert_eq!(plutus_to_address(&data, Network::Testnet), Some(expected));
+
+        // an address is a constr with index 0
+        let wrong = constr(1, vec![script_credential(0x11), nothing()]);
+        assert_eq!(plutus_to_address(&wrong, Network::Testnet), None);
+    }
+
+    #[test]
+    fn follows_owner_path() {
+        let owner = constr(0, vec![pubkey_credential(0x11), nothing()]);
+
+        let expected = ShelleyAddress::new(
+            Network::Mainnet,
+            ShelleyPaymentPart::key_hash(Hash::new([0x11; 28])),
+            ShelleyDelegationPart::Null,
+        );
+
+        // by default the owner is the second field of the root constr
+        let datum = constr(0, vec![int(1), owner.clone()]);
+        assert_eq!(
+            reducer(None).datum_to_address(&datum),
+            Some(expected.clone())
+        );
+
+        // the owner nested in the first field of the third field of a constr 2
+        let datum = constr(2, vec![int(1), int(2), constr(0, vec![owner])]);
+
+        let path = vec![
+            DatumPathStep {
+                constructor: Some(2),
+                field: 2,
+            },
+            DatumPathStep {
+                constructor: None,
+                field: 0,
+            },
+        ];
+
+        assert_eq!(
+            reducer(Some(path.clone())).datum_to_address(&datum),
+            Some(expected)
+        );
+
+        // a step expecting a different constructor doesn't match
+        let mut wrong = path;
+        wrong[0].constructor = Some(1);
+        assert_eq!(reducer(Some(wrong)).datum_to_address(&datum), None);
+
+        // fields beyond the end of the constr don't match either
+        assert_eq!(reducer(None).datum_to_address(&constr(0, vec![])), None);
+    }
 }
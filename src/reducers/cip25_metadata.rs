@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use pallas::ledger::primitives::alonzo::Metadatum;
+use pallas::ledger::traverse::{MultiEraBlock, MultiEraTx};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::crosscut::metadata::metadatum_to_json;
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+const CIP25_LABEL: u64 = 721;
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    /// hex of the policies to track, every policy if absent
+    pub policy_ids: Option<Vec<String>>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+/// Policy ids and asset names are text in v1 (hex and utf-8 respectively) and
+/// raw bytes in v2, returns the raw bytes in hex
+fn cip25_key(key: &Metadatum, is_policy: bool) -> Option<String> {
+    match key {
+        Metadatum::Text(x) if is_policy => Some(x.to_lowercase()),
+        Metadatum::Text(x) => Some(hex::encode(x.as_bytes())),
+        Metadatum::Bytes(x) => Some(hex::encode(x.as_slice())),
+        _ => None,
+    }
+}
+
+/// Metadata of each asset in a label 721 value, keyed by (policy, name) in hex
+fn parse_cip25(value: &Metadatum) -> HashMap<(String, String), &Metadatum> {
+    let mut assets = HashMap::new();
+
+    let policies = match value {
+        Metadatum::Map(x) => x,
+        _ => return assets,
+    };
+
+    for (policy, names) in policies.iter() {
+        // skips the `version` entry and anything malformed
+        let (policy, names) = match (cip25_key(policy, true), names) {
+            (Some(policy), Metadatum::Map(names)) => (policy, names),
+            _ => continue,
+        };
+
+        for (name, metadata) in names.iter() {
+            if let Some(name) = cip25_key(name, false) {
+                assets.insert((policy.clone(), name), metadata);
+            }
+        }
+    }
+
+    assets
+}
+
+/// Assets minted (not burned) by the tx, as (policy, name) in hex
+fn minted_assets(tx: &MultiEraTx) -> Vec<(String, String)> {
+    let mint = match (tx.as_babbage(), tx.as_alonzo()) {
+        (Some(x), _) => x.transaction_body.mint.clone(),
+        (_, Some(x)) => x.transaction_body.mint.clone(),
+        _ => None,
+    };
+
+    let mint = match mint {
+        Some(x) => x,
+        None => return vec![],
+    };
+
+    mint.iter()
+        .flat_map(|(policy, names)| {
+            names
+                .iter()
+                .filter(|(_, quantity)| *quantity > 0)
+                .map(|(name, _)| (hex::encode(policy), hex::encode(name.as_slice())))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+impl Reducer {
+    fn is_tracked(&self, policy: &str) -> bool {
+        match &self.config.policy_ids {
+            // policy ids are hex, configs might have them in either case
+            Some(allowed) => allowed.iter().any(|x| x.eq_ignore_ascii_case(policy)),
+            None => true,
+        }
+    }
+
+    fn tx_commands(&self, tx: &MultiEraTx, point: &Point) -> Vec<CRDTCommand> {
+        // the mint of a failed script validation doesn't take effect
+        if !tx.is_valid() {
+            return vec![];
+        }
+
+        let metadata = tx.metadata();
+
+        let metadata = match metadata.as_alonzo() {
+            Some(x) => x,
+            None => return vec![],
+        };
+
+        let cip25 = match metadata.iter().find(|(label, _)| *label == CIP25_LABEL) {
+            Some((_, value)) => parse_cip25(value),
+            None => return vec![],
+        };
+
+        minted_assets(tx)
+            .into_iter()
+            .filter(|(policy, _)| self.is_tracked(policy))
+            .filter_map(|(policy, name)| {
+                let asset_metadata = cip25.get(&(policy.clone(), name.clone()))?;
+
+                let key = match &self.config.key_prefix {
+                    Some(prefix) => format!("{}.{}.{}", prefix, policy, name),
+                    None => format!("{}.{}", policy, name),
+                };
+
+                // re-minting with new metadata replaces the previous one
+                Some(CRDTCommand::AnyWriteWins(
+                    key,
+                    metadatum_to_json(asset_metadata).into(),
+                    point.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    fn process_tx(
+        &mut self,
+        tx: &MultiEraTx,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for crdt in self.tx_commands(tx, point) {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                self.process_tx(&tx, &point, output)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::Cip25Metadata(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::codec::utils::KeyValuePairs;
+    use pallas::crypto::hash::Hash;
+
+    use super::*;
+    use crate::reducers::fixtures::{babbage_tx_with_outputs, decode_tx, multiasset};
+
+    const POLICY: &str = "d5e6bf0500378d4f0da4e8dde6becec7621cd8cbf5cbb9b87013d4cc";
+
+    fn map(entries: Vec<(Metadatum, Metadatum)>) -> Metadatum {
+        Metadatum::Map(KeyValuePairs::Def(entries))
+    }
+
+    #[test]
+    fn v1_and_v2_names_match() {
+        let asset = || {
+            map(vec![(
+                Metadatum::Text("name".into()),
+                Metadatum::Text("Nft #1".into()),
+            )])
+        };
+
+        let v1 = map(vec![
+            (
+                Metadatum::Text(POLICY.to_uppercase()),
+                map(vec![(Metadatum::Text("Nft1".into()), asset())]),
+            ),
+            (
+                Metadatum::Text("version".into()),
+                Metadatum::Text("1.0".into()),
+            ),
+        ]);
+
+        let v2 = map(vec![(
+            Metadatum::Bytes(hex::decode(POLICY).unwrap().into()),
+            map(vec![(Metadatum::Bytes(b"Nft1".to_vec().into()), asset())]),
+        )]);
+
+        let key = (POLICY.to_string(), hex::encode("Nft1"));
+
+        for parsed in [parse_cip25(&v1), parse_cip25(&v2)] {
+            assert_eq!(parsed.len(), 1);
+            assert_eq!(
+                metadatum_to_json(parsed[&key]),
+                serde_json::json!({ "name": "Nft #1" })
+            );
+        }
+    }
+
+    fn reducer() -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: None,
+                policy_ids: Some(vec![POLICY.to_uppercase()]),
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    /// Tx minting `Nft1` and burning `Old`, with v2 metadata for both
+    fn mint_tx(valid: bool) -> Vec<u8> {
+        let policy: Hash<28> = POLICY.parse().unwrap();
+        let mint = multiasset(&[(policy, b"Nft1", 1), (policy, b"Old", -1)]);
+
+        let aux = format!(
+            "a1 1902d1 a1 581c{} a2 444e667431 a1 646e616d65 664e6674202331 434f6c64 a1 646e616d65 634f6c64",
+            POLICY
+        );
+
+        babbage_tx_with_outputs(&[], Some(&mint), valid, Some(&aux))
+    }
+
+    #[test]
+    fn matches_metadata_to_minted_assets() {
+        let cbor = mint_tx(true);
+        let tx = decode_tx(&cbor);
+        let point = Point::Specific(100, vec![0xff; 32]);
+
+        let commands = reducer().tx_commands(&tx, &point);
+
+        // the burned asset keeps its metadata
+        match commands.as_slice() {
+            [CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _)] => {
+                assert_eq!(key, &format!("{}.{}", POLICY, hex::encode("Nft1")));
+                assert_eq!(value, &serde_json::json!({ "name": "Nft #1" }));
+            }
+            other => panic!("unexpected commands {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ignores_invalid_txs() {
+        let cbor = mint_tx(false);
+        let tx = decode_tx(&cbor);
+        let point = Point::Specific(100, vec![0xff; 32]);
+
+        assert!(reducer().tx_commands(&tx, &point).is_empty());
+    }
+}
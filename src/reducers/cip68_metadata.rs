@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+
+use pallas::ledger::primitives::alonzo::PlutusData;
+use pallas::ledger::traverse::{Asset, MultiEraBlock, MultiEraOutput, MultiEraTx, OutputRef};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::crosscut::plutus::{constr_index, data_to_json};
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+/// CIP-67 prefixes of the asset names, for labels 100, 222, 333 and 444
+const REFERENCE_NFT_LABEL: [u8; 4] = [0x00, 0x06, 0x43, 0xb0];
+const USER_NFT_LABEL: [u8; 4] = [0x00, 0x0d, 0xe1, 0x40];
+const USER_FT_LABEL: [u8; 4] = [0x00, 0x14, 0xdf, 0x10];
+const USER_RFT_LABEL: [u8; 4] = [0x00, 0x1b, 0xc2, 0x80];
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    /// hex of the policies to track, every policy if absent
+    pub policy_ids: Option<Vec<String>>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+/// Splits a CIP-68 asset name into its label and the name shared by the
+/// reference and user tokens
+fn split_label(name: &[u8]) -> Option<([u8; 4], &[u8])> {
+    let label: [u8; 4] = name.get(..4)?.try_into().ok()?;
+
+    match label {
+        REFERENCE_NFT_LABEL | USER_NFT_LABEL | USER_FT_LABEL | USER_RFT_LABEL => {
+            Some((label, &name[4..]))
+        }
+        _ => None,
+    }
+}
+
+/// Metadata values are utf-8 bytes by convention, anything else is hex
+fn metadata_to_json(data: &PlutusData) -> Value {
+    match data {
+        PlutusData::BoundedBytes(x) => match std::str::from_utf8(x.as_slice()) {
+            Ok(text) => json!(text),
+            Err(_) => json!(hex::encode(x.as_slice())),
+        },
+        PlutusData::Map(x) => {
+            let object: Map<_, _> = x
+                .iter()
+                .map(|(k, v)| {
+                    let key = match metadata_to_json(k) {
+                        Value::String(x) => x,
+                        other => other.to_string(),
+                    };
+
+                    (key, metadata_to_json(v))
+                })
+                .collect();
+
+            Value::Object(object)
+        }
+        PlutusData::Array(x) => Value::Array(x.iter().map(metadata_to_json).collect()),
+        other => data_to_json(other),
+    }
+}
+
+/// Decodes the `Constr 0 [metadata, version, extra]` datum of a reference token
+fn decode_datum(datum: &PlutusData) -> Result<Value, crate::Error> {
+    let fields = match datum {
+        PlutusData::Constr(x) if constr_index(x) == Some(0) && x.fields.len() >= 2 => &x.fields,
+        _ => {
+            return Err(crate::Error::message(format!(
+                "invalid CIP-68 datum: {}",
+                data_to_json(datum)
+            )))
+        }
+    };
+
+    Ok(json!({
+        "metadata": metadata_to_json(&fields[0]),
+        "version": data_to_json(&fields[1]),
+        "extra": fields.get(2).map(data_to_json),
+    }))
+}
+
+impl Reducer {
+    fn key(&self, policy: &str, name: &[u8]) -> String {
+        match &self.config.key_prefix {
+            Some(prefix) => format!("{}.{}.{}", prefix, policy, hex::encode(name)),
+            None => format!("{}.{}", policy, hex::encode(name)),
+        }
+    }
+
+    /// CIP-68 tokens held by the output, as (policy, label, shared name)
+    fn cip68_assets(&self, output: &MultiEraOutput) -> Vec<(String, [u8; 4], Vec<u8>)> {
+        output
+            .non_ada_assets()
+            .into_iter()
+            .filter_map(|asset| match asset {
+                Asset::NativeAsset(policy, name, _) => {
+                    let (label, shared) = split_label(&name)?;
+                    Some((hex::encode(policy), label, shared.to_vec()))
+                }
+                Asset::Ada(_) => None,
+            })
+            .filter(|(policy, _, _)| match &self.config.policy_ids {
+                Some(allowed) => allowed.contains(policy),
+                None => true,
+            })
+            .collect()
+    }
+
+    fn tx_commands(
+        &self,
+        ctx: &model::BlockContext,
+        tx: &MultiEraTx,
+        point: &Point,
+    ) -> Result<Vec<CRDTCommand>, crate::Error> {
+        let mut commands = vec![];
+        let mut spent_refs = HashSet::new();
+
+        for input in tx.consumes().iter().map(|i| i.output_ref()) {
+            let utxo = ctx.find_utxo(&input).apply_policy(&self.policy)?;
+
+            if let Some(utxo) = utxo {
+                for (policy, label, name) in self.cip68_assets(&utxo) {
+                    if label == REFERENCE_NFT_LABEL {
+                        spent_refs.insert(self.key(&policy, &name));
+                    }
+                }
+            }
+        }
+
+        for (idx, produced) in tx.produces() {
+            for (policy, label, name) in self.cip68_assets(&produced) {
+                let key = self.key(&policy, &name);
+
+                let class = match label {
+                    USER_NFT_LABEL => "nft",
+                    USER_FT_LABEL => "ft",
+                    USER_RFT_LABEL => "rft",
+                    // the reference token moved or was minted, its datum has
+                    // the latest metadata
+                    _ => {
+                        spent_refs.remove(&key);
+
+                        let datum =
+                            model::resolve_datum(ctx, &produced).apply_policy(&self.policy)?;
+
+                        // anyone can mint a token with the reference label,
+                        // so a missing or malformed datum is not fatal
+                        let datum = match datum {
+                            Some(Some(x)) => x,
+                            Some(None) => {
+                                log::warn!("skipping reference token {} without datum", key);
+                                continue;
+                            }
+                            None => continue,
+                        };
+
+                        let mut value = match decode_datum(&datum) {
+                            Ok(x) => x,
+                            Err(err) => {
+                                log::warn!("skipping reference token {}: {}", key, err);
+                                continue;
+                            }
+                        };
+
+                        value["utxo"] = json!(OutputRef::new(tx.hash(), idx as u64).to_string());
+
+                        commands.push(CRDTCommand::AnyWriteWins(key, value.into(), point.clone()));
+
+                        continue;
+                    }
+                };
+
+                // the kind of user token that the reference token describes
+                commands.push(CRDTCommand::AnyWriteWins(
+                    format!("{}.class", key),
+                    class.to_string().into(),
+                    point.clone(),
+                ));
+            }
+        }
+
+        // reference tokens spent and not sent anywhere else were burned, along
+        // with the class of the user token they describe
+        for key in spent_refs {
+            commands.push(CRDTCommand::KeyRemove(
+                format!("{}.class", key),
+                point.clone(),
+            ));
+
+            commands.push(CRDTCommand::KeyRemove(key, point.clone()));
+        }
+
+        Ok(commands)
+    }
+
+    fn process_tx(
+        &mut self,
+        ctx: &model::BlockContext,
+        tx: &MultiEraTx,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for crdt in self.tx_commands(ctx, tx, point).or_panic()? {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                self.process_tx(ctx, &tx, &point, output)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::Cip68Metadata(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::codec::utils::KeyValuePairs;
+    use pallas::crypto::hash::Hash;
+    use pallas::ledger::primitives::alonzo::{BigInt, Constr};
+    use pallas::ledger::traverse::Era;
+
+    use super::*;
+    use crate::reducers::fixtures::{
+        babbage_output, babbage_tx_with_outputs, decode_tx, dummy_input, ENTERPRISE_ADDRESS,
+    };
+
+    fn constr(index: u64, fields: Vec<PlutusData>) -> PlutusData {
+        PlutusData::Constr(Constr {
+            tag: 121 + index,
+            any_constructor: None,
+            fields: fields.into(),
+        })
+    }
+
+    fn bytes(value: &[u8]) -> PlutusData {
+        PlutusData::BoundedBytes(value.to_vec().into())
+    }
+
+    fn metadata(pairs: Vec<(&str, PlutusData)>) -> PlutusData {
+        let pairs = pairs
+            .into_iter()
+            .map(|(k, v)| (bytes(k.as_bytes()), v))
+            .collect();
+        PlutusData::Map(KeyValuePairs::Def(pairs))
+    }
+
+    #[test]
+    fn splits_cip67_labels() {
+        let name = [&REFERENCE_NFT_LABEL[..], b"token"].concat();
+        assert_eq!(
+            split_label(&name),
+            Some((REFERENCE_NFT_LABEL, &b"token"[..]))
+        );
+
+        let name = [&USER_FT_LABEL[..], b"token"].concat();
+        assert_eq!(split_label(&name), Some((USER_FT_LABEL, &b"token"[..])));
+
+        // the label alone is a valid name
+        assert_eq!(
+            split_label(&USER_NFT_LABEL),
+            Some((USER_NFT_LABEL, &b""[..]))
+        );
+
+        assert_eq!(split_label(b"token"), None);
+        assert_eq!(split_label(&[0x00, 0x06]), None);
+    }
+
+    #[test]
+    fn renders_utf8_and_binary_metadata() {
+        let data = metadata(vec![
+            ("name", bytes(b"My NFT")),
+            ("hash", bytes(&[0xff, 0xfe])),
+            ("files", PlutusData::Array(vec![bytes(b"ipfs://x")].into())),
+        ]);
+
+        assert_eq!(
+            metadata_to_json(&data),
+            json!({ "name": "My NFT", "hash": "fffe", "files": ["ipfs://x"] })
+        );
+    }
+
+    #[test]
+    fn decodes_reference_datum() {
+        let version = PlutusData::BigInt(BigInt::Int(1u64.into()));
+
+        let datum = constr(
+            0,
+            vec![metadata(vec![("name", bytes(b"My NFT"))]), version.clone()],
+        );
+
+        assert_eq!(
+            decode_datum(&datum).unwrap(),
+            json!({ "metadata": { "name": "My NFT" }, "version": 1, "extra": null })
+        );
+
+        // the datum is a constr 0
+        let wrong = constr(1, vec![metadata(vec![]), version]);
+        assert!(decode_datum(&wrong).is_err());
+
+        // with at least the metadata and the version
+        let short = constr(0, vec![metadata(vec![])]);
+        assert!(decode_datum(&short).is_err());
+    }
+
+    #[test]
+    fn skips_reference_tokens_with_bad_datums() {
+        let policy = Hash::<28>::new([0x44; 28]);
+        let asset = |name: &[u8]| [&REFERENCE_NFT_LABEL[..], name].concat();
+
+        let (junk, missing, good) = (asset(b"A"), asset(b"B"), asset(b"C"));
+
+        // constr 1 [] isn't a CIP-68 datum, constr 0 [{ "name": "My NFT" }, 1] is
+        let outputs = [
+            babbage_output(
+                ENTERPRISE_ADDRESS,
+                2000000,
+                &[(policy, &junk[..], 1)],
+                Some("d87a80"),
+            ),
+            babbage_output(
+                ENTERPRISE_ADDRESS,
+                2000000,
+                &[(policy, &missing[..], 1)],
+                None,
+            ),
+            babbage_output(
+                ENTERPRISE_ADDRESS,
+                2000000,
+                &[(policy, &good[..], 1)],
+                Some("d879 82 a1 446e616d65 464d79204e4654 01"),
+            ),
+        ];
+
+        let cbor = babbage_tx_with_outputs(&outputs, None, true, None);
+        let tx = decode_tx(&cbor);
+
+        let mut ctx = model::BlockContext::default();
+        let input = babbage_output(ENTERPRISE_ADDRESS, 7000000, &[], None);
+        ctx.import_ref_output(&dummy_input(), Era::Babbage, input);
+
+        let reducer = Reducer {
+            config: Config {
+                key_prefix: None,
+                policy_ids: None,
+                filter: None,
+            },
+            policy: Default::default(),
+        };
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        let commands = reducer.tx_commands(&ctx, &tx, &point).unwrap();
+
+        // only the token with a valid datum is stored, under its shared name
+        match commands.as_slice() {
+            [CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _)] => {
+                assert_eq!(key, &format!("{}.{}", policy, hex::encode("C")));
+                assert_eq!(
+                    value,
+                    &json!({
+                        "metadata": { "name": "My NFT" },
+                        "version": 1,
+                        "extra": null,
+                        "utxo": OutputRef::new(tx.hash(), 2).to_string(),
+                    })
+                );
+            }
+            other => panic!("unexpected commands {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use pallas::ledger::traverse::MultiEraOutput;
+use pallas::ledger::traverse::{MultiEraBlock, OutputRef};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::crosscut::plutus::{data_to_json, DatumSchema};
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    /// bech32 of the script address holding the datums
+    pub script_address: String,
+    /// CIP-57 blueprint (eg: Aiken's `plutus.json`) with the datum schema,
+    /// datums are decoded without a schema if absent
+    pub blueprint_path: Option<String>,
+    /// Title of the validator in the blueprint
+    pub validator: Option<String>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    schema: Option<DatumSchema>,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+impl Reducer {
+    fn key(&self, utxo_ref: &OutputRef) -> String {
+        match &self.config.key_prefix {
+            Some(prefix) => format!("{}.{}", prefix, utxo_ref),
+            None => utxo_ref.to_string(),
+        }
+    }
+
+    fn is_script_output(&self, output: &MultiEraOutput) -> Result<bool, crate::Error> {
+        let address = output.address().map_err(crate::Error::ledger)?;
+        Ok(address.to_string() == self.config.script_address)
+    }
+
+    fn process_consumed_txo(
+        &mut self,
+        ctx: &model::BlockContext,
+        input: &OutputRef,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let utxo = ctx.find_utxo(input).apply_policy(&self.policy).or_panic()?;
+
+        let utxo = match utxo {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        if !self.is_script_output(&utxo).or_panic()? {
+            return Ok(());
+        }
+
+        output.send(gasket::messaging::Message::from(CRDTCommand::KeyRemove(
+            self.key(input),
+            point.clone(),
+        )))
+    }
+
+    fn process_produced_txo(
+        &mut self,
+        ctx: &model::BlockContext,
+        utxo_ref: &OutputRef,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let crdt = self
+            .produced_command(ctx, utxo_ref, tx_output, point)
+            .or_panic()?;
+
+        match crdt {
+            Some(crdt) => output.send(gasket::messaging::Message::from(crdt)),
+            None => Ok(()),
+        }
+    }
+
+    /// The datum of the new script output, decoded following the schema
+    fn produced_command(
+        &self,
+        ctx: &model::BlockContext,
+        utxo_ref: &OutputRef,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+    ) -> Result<Option<CRDTCommand>, crate::Error> {
+        if !self.is_script_output(tx_output)? {
+            return Ok(None);
+        }
+
+        let datum = model::resolve_datum(ctx, tx_output)
+            .apply_policy(&self.policy)?
+            .flatten();
+
+        let datum = match datum {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        // anyone can lock an output at the script address with an arbitrary
+        // datum, those that don't follow the schema are left out
+        let value = match &self.schema {
+            Some(schema) => match schema.decode(&datum) {
+                Ok(x) => x,
+                Err(err) => {
+                    log::warn!("skipping datum of {}: {}", utxo_ref, err);
+                    return Ok(None);
+                }
+            },
+            None => data_to_json(&datum),
+        };
+
+        Ok(Some(CRDTCommand::AnyWriteWins(
+            self.key(utxo_ref),
+            value.into(),
+            point.clone(),
+        )))
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                for consumed in tx.consumes().iter().map(|i| i.output_ref()) {
+                    self.process_consumed_txo(ctx, &consumed, &point, output)?;
+                }
+
+                for (idx, produced) in tx.produces() {
+                    let utxo_ref = OutputRef::new(tx.hash(), idx as u64);
+                    self.process_produced_txo(ctx, &utxo_ref, &produced, &point, output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    fn load_schema(&self) -> Result<Option<DatumSchema>, crate::Error> {
+        let path = match &self.blueprint_path {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let validator = self.validator.as_deref().ok_or_else(|| {
+            crate::Error::config("validator is required along with blueprint_path")
+        })?;
+
+        DatumSchema::from_blueprint(path, validator).map(Some)
+    }
+
+    /// Fails if the blueprint can't be loaded, so a bad path is noticed at
+    /// startup instead of at the first block
+    pub fn plugin(
+        self,
+        policy: &crosscut::policies::RuntimePolicy,
+    ) -> Result<super::Reducer, crate::Error> {
+        let reducer = Reducer {
+            schema: self.load_schema()?,
+            config: self,
+            policy: policy.clone(),
+        };
+
+        Ok(super::Reducer::DatumByUtxo(reducer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::crypto::hash::Hash;
+    use serde_json::json;
+
+    use super::*;
+    use crate::reducers::fixtures::{babbage_output, decode_output, ENTERPRISE_ADDRESS};
+
+    fn reducer() -> Reducer {
+        let schema = json!({
+            "title": "Datum",
+            "dataType": "constructor",
+            "index": 0,
+            "fields": [{ "title": "owner", "dataType": "bytes" }]
+        });
+
+        Reducer {
+            config: Config {
+                key_prefix: Some("datums".to_string()),
+                script_address: ENTERPRISE_ADDRESS.to_string(),
+                blueprint_path: None,
+                validator: None,
+                filter: None,
+            },
+            schema: Some(DatumSchema::new(schema, Default::default())),
+            policy: Default::default(),
+        }
+    }
+
+    #[test]
+    fn decodes_datums_following_the_schema() {
+        // constr 0 [#cafe]
+        let cbor = babbage_output(ENTERPRISE_ADDRESS, 2000000, &[], Some("d879 81 42cafe"));
+        let output = decode_output(&cbor);
+
+        let utxo_ref = OutputRef::new(Hash::new([0xaa; 32]), 1);
+        let point = Point::Specific(100, vec![0xff; 32]);
+
+        let crdt = reducer()
+            .produced_command(&model::BlockContext::default(), &utxo_ref, &output, &point)
+            .unwrap();
+
+        match crdt {
+            Some(CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _)) => {
+                assert_eq!(key, format!("datums.{}", utxo_ref));
+                assert_eq!(
+                    value,
+                    json!({ "constructor": "Datum", "fields": { "owner": "cafe" } })
+                );
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skips_datums_off_the_schema() {
+        // constr 1 [], which any output at the script address could hold
+        let cbor = babbage_output(ENTERPRISE_ADDRESS, 2000000, &[], Some("d87a80"));
+        let output = decode_output(&cbor);
+
+        let utxo_ref = OutputRef::new(Hash::new([0xaa; 32]), 1);
+        let point = Point::Specific(100, vec![0xff; 32]);
+
+        let crdt = reducer()
+            .produced_command(&model::BlockContext::default(), &utxo_ref, &output, &point)
+            .unwrap();
+
+        assert!(crdt.is_none());
+    }
+}
@@ -0,0 +1,174 @@
+//! Hand-made transactions for the tests of the reducers, encoded as hex to
+//! keep them close to the CDDL of the ledger
+
+use pallas::crypto::hash::Hash;
+use pallas::ledger::addresses::Address;
+use pallas::ledger::traverse::{Era, MultiEraOutput, MultiEraTx, OutputRef};
+
+/// Base address with a payment key hash of `0x11` bytes and a stake key hash
+/// of `0x22` bytes
+pub const BASE_ADDRESS: &str = "addr1qyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyfzyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3qd5sgwv";
+
+/// Enterprise address with the same payment key hash, without stake part
+pub const ENTERPRISE_ADDRESS: &str = "addr1vyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygatvcjl";
+
+/// CBOR of a Babbage tx spending a dummy input, without outputs. `certs` is
+/// the hex of the certificates array and `aux` the one of the auxiliary data.
+pub fn babbage_tx(certs: Option<&str>, valid: bool, aux: Option<&str>) -> Vec<u8> {
+    let mut fields = vec![(1, "80".to_string())];
+
+    if let Some(certs) = certs {
+        fields.push((4, certs.to_string()));
+    }
+
+    encode_tx(fields, valid, aux)
+}
+
+/// CBOR of a Babbage tx spending the dummy input and producing `outputs`.
+/// `mint` is the hex of the multiasset map minted or burned by the tx.
+pub fn babbage_tx_with_outputs(
+    outputs: &[Vec<u8>],
+    mint: Option<&str>,
+    valid: bool,
+    aux: Option<&str>,
+) -> Vec<u8> {
+    let array = format!(
+        "{}{}",
+        head(4, outputs.len() as u64),
+        outputs.iter().map(hex::encode).collect::<String>()
+    );
+
+    let mut fields = vec![(1, array)];
+
+    if let Some(mint) = mint {
+        fields.push((9, mint.to_string()));
+    }
+
+    encode_tx(fields, valid, aux)
+}
+
+/// Reference to the input spent by every tx of the fixtures
+pub fn dummy_input() -> OutputRef {
+    OutputRef::new(Hash::new([0xaa; 32]), 0)
+}
+
+/// Builds the tx body from the given fields besides the inputs and the fee
+fn encode_tx(mut fields: Vec<(u64, String)>, valid: bool, aux: Option<&str>) -> Vec<u8> {
+    let input = format!("81 825820{} 00", "aa".repeat(32));
+
+    fields.push((0, input));
+    fields.push((2, "1a00029810".to_string()));
+    fields.sort_by_key(|(key, _)| *key);
+
+    let body: String = fields
+        .iter()
+        .map(|(key, value)| format!("{}{}", head(0, *key), value))
+        .collect();
+
+    let valid = if valid { "f5" } else { "f4" };
+    let tx = format!(
+        "84 {}{} a0 {} {}",
+        head(5, fields.len() as u64),
+        body,
+        valid,
+        aux.unwrap_or("f6")
+    );
+
+    hex::decode(tx.replace(' ', "")).unwrap()
+}
+
+pub fn decode_tx(cbor: &[u8]) -> MultiEraTx {
+    MultiEraTx::decode(Era::Babbage, cbor).unwrap()
+}
+
+/// Hex of a 28 bytes hash (key, script or pool) filled with `byte`
+pub fn hash28(byte: u8) -> String {
+    format!("581c{}", hex::encode([byte; 28]))
+}
+
+/// Hex of the head of a CBOR data item with the given major type and argument
+fn head(major: u8, value: u64) -> String {
+    let major = major << 5;
+
+    match value {
+        0..=23 => format!("{:02x}", major | value as u8),
+        24..=0xff => format!("{:02x}{:02x}", major | 24, value),
+        0x100..=0xffff => format!("{:02x}{:04x}", major | 25, value),
+        0x10000..=0xffff_ffff => format!("{:02x}{:08x}", major | 26, value),
+        _ => format!("{:02x}{:016x}", major | 27, value),
+    }
+}
+
+fn int(value: i64) -> String {
+    match value {
+        x if x >= 0 => head(0, x as u64),
+        x => head(1, (-1 - x) as u64),
+    }
+}
+
+fn bytes(value: &[u8]) -> String {
+    format!("{}{}", head(2, value.len() as u64), hex::encode(value))
+}
+
+/// Hex of a multiasset map, assets of the same policy are grouped in the order
+/// they are listed
+pub fn multiasset(assets: &[(Hash<28>, &[u8], i64)]) -> String {
+    let mut policies: Vec<(Hash<28>, Vec<String>)> = vec![];
+
+    for (policy, name, qty) in assets {
+        let entry = format!("{}{}", bytes(name), int(*qty));
+
+        match policies.iter_mut().find(|(p, _)| p == policy) {
+            Some((_, names)) => names.push(entry),
+            None => policies.push((*policy, vec![entry])),
+        }
+    }
+
+    let entries: String = policies
+        .iter()
+        .map(|(policy, names)| {
+            let names_head = head(5, names.len() as u64);
+            format!("{}{}{}", bytes(policy.as_ref()), names_head, names.concat())
+        })
+        .collect();
+
+    format!("{}{}", head(5, policies.len() as u64), entries)
+}
+
+/// CBOR of a Babbage output holding `lovelace` and `assets`, with `datum` as
+/// the hex of its inline datum
+pub fn babbage_output(
+    address: &str,
+    lovelace: u64,
+    assets: &[(Hash<28>, &[u8], u64)],
+    datum: Option<&str>,
+) -> Vec<u8> {
+    let address = Address::from_bech32(address).unwrap().to_vec();
+
+    let value = match assets {
+        [] => head(0, lovelace),
+        _ => {
+            let assets: Vec<_> = assets
+                .iter()
+                .map(|(policy, name, qty)| (*policy, *name, *qty as i64))
+                .collect();
+
+            format!("82 {} {}", head(0, lovelace), multiasset(&assets))
+        }
+    };
+
+    let output = match datum {
+        Some(datum) => {
+            let datum = hex::decode(datum.replace(' ', "")).unwrap();
+            let datum = format!("82 01 d818 {}", bytes(&datum));
+            format!("a3 00 {} 01 {} 02 {}", bytes(&address), value, datum)
+        }
+        None => format!("a2 00 {} 01 {}", bytes(&address), value),
+    };
+
+    hex::decode(output.replace(' ', "")).unwrap()
+}
+
+pub fn decode_output(cbor: &[u8]) -> MultiEraOutput {
+    MultiEraOutput::decode(Era::Babbage, cbor).unwrap()
+}
@@ -0,0 +1,189 @@
+use pallas::ledger::traverse::{MultiEraBlock, MultiEraTx};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::crosscut::metadata::metadatum_to_json;
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    /// Metadata labels to keep (eg: 674 for messages), every label if absent
+    pub labels: Option<Vec<u64>>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+impl Reducer {
+    fn key(&self, suffix: String) -> String {
+        match &self.config.key_prefix {
+            Some(prefix) => format!("{}.{}", prefix, suffix),
+            None => suffix,
+        }
+    }
+
+    fn tx_commands(&self, tx: &MultiEraTx, point: &Point) -> Vec<CRDTCommand> {
+        let mut commands = vec![];
+
+        let metadata = tx.metadata();
+
+        let metadata = match metadata.as_alonzo() {
+            Some(x) => x,
+            None => return commands,
+        };
+
+        let tx_hash = tx.hash().to_string();
+
+        for (label, value) in metadata.iter() {
+            if let Some(labels) = &self.config.labels {
+                if !labels.contains(label) {
+                    continue;
+                }
+            }
+
+            // the metadata of the tx under the label
+            commands.push(CRDTCommand::AnyWriteWins(
+                self.key(format!("{}.{}", tx_hash, label)),
+                metadatum_to_json(value).into(),
+                point.clone(),
+            ));
+
+            // and the txs that used the label
+            commands.push(CRDTCommand::SetAdd(
+                self.key(label.to_string()),
+                tx_hash.clone(),
+                point.clone(),
+            ));
+        }
+
+        commands
+    }
+
+    fn process_tx(
+        &mut self,
+        tx: &MultiEraTx,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for crdt in self.tx_commands(tx, point) {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                self.process_tx(&tx, &point, output)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::MetadataByTx(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::reducers::fixtures::{babbage_tx, decode_tx};
+
+    /// Shelley-style metadata: `{ 674: { "msg": ["hi"] }, 721: "x" }`
+    const METADATA: &str = "a2 1902a2 a1 636d7367 81 626869 1902d1 6178";
+
+    fn reducer(labels: Option<Vec<u64>>) -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: Some("meta".to_string()),
+                labels,
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    /// Keys written by the commands, along with the JSON or member written
+    fn writes(commands: Vec<CRDTCommand>) -> Vec<(String, serde_json::Value)> {
+        commands
+            .into_iter()
+            .map(|crdt| match crdt {
+                CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _) => (key, value),
+                CRDTCommand::SetAdd(key, member, _) => (key, json!(member)),
+                other => panic!("unexpected command {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keys_metadata_by_tx_and_label() {
+        let cbor = babbage_tx(None, true, Some(METADATA));
+        let tx = decode_tx(&cbor);
+        let hash = tx.hash().to_string();
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        let commands = reducer(None).tx_commands(&tx, &point);
+
+        assert_eq!(
+            writes(commands),
+            vec![
+                (format!("meta.{}.674", hash), json!({ "msg": ["hi"] })),
+                ("meta.674".to_string(), json!(hash)),
+                (format!("meta.{}.721", hash), json!("x")),
+                ("meta.721".to_string(), json!(hash)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_only_allowed_labels() {
+        let cbor = babbage_tx(None, true, Some(METADATA));
+        let tx = decode_tx(&cbor);
+        let hash = tx.hash().to_string();
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        let commands = reducer(Some(vec![721])).tx_commands(&tx, &point);
+
+        assert_eq!(
+            writes(commands),
+            vec![
+                (format!("meta.{}.721", hash), json!("x")),
+                ("meta.721".to_string(), json!(hash)),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_txs_without_metadata() {
+        let cbor = babbage_tx(None, true, None);
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        assert!(reducer(None)
+            .tx_commands(&decode_tx(&cbor), &point)
+            .is_empty());
+    }
+}
@@ -11,7 +11,17 @@ type OutputPort = gasket::messaging::OutputPort<model::CRDTCommand>;
 
 pub mod balance_by_address;
 pub mod balance_by_genius_stake;
+pub mod cip25_metadata;
+pub mod cip68_metadata;
+pub mod datum_by_utxo;
+#[cfg(test)]
+mod fixtures;
 pub mod macros;
+pub mod metadata_by_tx;
+pub mod pool_by_stake;
+pub mod pool_metadata;
+pub mod utxo_by_address;
+pub mod utxo_by_asset;
 mod worker;
 
 #[derive(Deserialize)]
@@ -19,18 +29,36 @@ mod worker;
 pub enum Config {
     BalanceByAddress(balance_by_address::Config),
     BalanceByGeniusStake(balance_by_genius_stake::Config),
+    UtxoByAddress(utxo_by_address::Config),
+    UtxoByAsset(utxo_by_asset::Config),
+    DatumByUtxo(datum_by_utxo::Config),
+    PoolByStake(pool_by_stake::Config),
+    PoolMetadata(pool_metadata::Config),
+    MetadataByTx(metadata_by_tx::Config),
+    Cip25Metadata(cip25_metadata::Config),
+    Cip68Metadata(cip68_metadata::Config),
 }
 
 impl Config {
     fn plugin(
         self,
-        _chain: &crosscut::ChainWellKnownInfo,
+        chain: &crosscut::ChainWellKnownInfo,
         policy: &crosscut::policies::RuntimePolicy,
-    ) -> Reducer {
-        match self {
+    ) -> Result<Reducer, crate::Error> {
+        let reducer = match self {
             Config::BalanceByAddress(c) => c.plugin(policy),
-            Config::BalanceByGeniusStake(c) => c.plugin(policy),
-        }
+            Config::BalanceByGeniusStake(c) => c.plugin(chain, policy),
+            Config::UtxoByAddress(c) => c.plugin(policy),
+            Config::UtxoByAsset(c) => c.plugin(policy),
+            Config::DatumByUtxo(c) => c.plugin(policy)?,
+            Config::PoolByStake(c) => c.plugin(policy),
+            Config::PoolMetadata(c) => c.plugin(chain, policy),
+            Config::MetadataByTx(c) => c.plugin(policy),
+            Config::Cip25Metadata(c) => c.plugin(policy),
+            Config::Cip68Metadata(c) => c.plugin(policy),
+        };
+
+        Ok(reducer)
     }
 }
 
@@ -46,16 +74,18 @@ impl Bootstrapper {
         configs: Vec<Config>,
         chain: &crosscut::ChainWellKnownInfo,
         policy: &crosscut::policies::RuntimePolicy,
-    ) -> Self {
-        Self {
-            reducers: configs
-                .into_iter()
-                .map(|x| x.plugin(chain, policy))
-                .collect(),
+    ) -> Result<Self, crate::Error> {
+        let reducers = configs
+            .into_iter()
+            .map(|x| x.plugin(chain, policy))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            reducers,
             input: Default::default(),
             output: Default::default(),
             policy: policy.clone(),
-        }
+        })
     }
 
     pub fn borrow_input_port(&mut self) -> &'_ mut InputPort {
@@ -82,6 +112,14 @@ impl Bootstrapper {
 pub enum Reducer {
     BalanceByAddress(balance_by_address::Reducer),
     BalanceByGeniusStake(balance_by_genius_stake::Reducer),
+    UtxoByAddress(utxo_by_address::Reducer),
+    UtxoByAsset(utxo_by_asset::Reducer),
+    DatumByUtxo(datum_by_utxo::Reducer),
+    PoolByStake(pool_by_stake::Reducer),
+    PoolMetadata(pool_metadata::Reducer),
+    MetadataByTx(metadata_by_tx::Reducer),
+    Cip25Metadata(cip25_metadata::Reducer),
+    Cip68Metadata(cip68_metadata::Reducer),
 }
 
 impl Reducer {
@@ -94,6 +132,14 @@ impl Reducer {
         match self {
             Reducer::BalanceByAddress(x) => x.reduce_block(block, ctx, output),
             Reducer::BalanceByGeniusStake(x) => x.reduce_block(block, ctx, output),
+            Reducer::UtxoByAddress(x) => x.reduce_block(block, ctx, output),
+            Reducer::UtxoByAsset(x) => x.reduce_block(block, ctx, output),
+            Reducer::DatumByUtxo(x) => x.reduce_block(block, ctx, output),
+            Reducer::PoolByStake(x) => x.reduce_block(block, ctx, output),
+            Reducer::PoolMetadata(x) => x.reduce_block(block, ctx, output),
+            Reducer::MetadataByTx(x) => x.reduce_block(block, ctx, output),
+            Reducer::Cip25Metadata(x) => x.reduce_block(block, ctx, output),
+            Reducer::Cip68Metadata(x) => x.reduce_block(block, ctx, output),
         }
     }
 }
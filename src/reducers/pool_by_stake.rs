@@ -0,0 +1,159 @@
+use pallas::ledger::primitives::alonzo::{Certificate, StakeCredential};
+use pallas::ledger::traverse::{MultiEraBlock, MultiEraTx};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+impl Reducer {
+    fn key(&self, credential: &StakeCredential) -> String {
+        let credential = match credential {
+            StakeCredential::AddrKeyhash(x) => hex::encode(x),
+            StakeCredential::Scripthash(x) => hex::encode(x),
+        };
+
+        match &self.config.key_prefix {
+            Some(prefix) => format!("{}.{}", prefix, credential),
+            None => credential,
+        }
+    }
+
+    fn tx_commands(&self, tx: &MultiEraTx, point: &Point) -> Vec<CRDTCommand> {
+        // certificates of a failed script validation don't take effect
+        if !tx.is_valid() {
+            return vec![];
+        }
+
+        // later certs overwrite earlier ones, storage keeps the latest write
+        // of each key and discards those of rolled back slots
+        tx.certs()
+            .iter()
+            .filter_map(|cert| match cert.as_alonzo() {
+                Some(Certificate::StakeDelegation(credential, pool)) => {
+                    Some(CRDTCommand::AnyWriteWins(
+                        self.key(credential),
+                        hex::encode(pool).into(),
+                        point.clone(),
+                    ))
+                }
+                Some(Certificate::StakeDeregistration(credential)) => {
+                    Some(CRDTCommand::KeyRemove(self.key(credential), point.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn process_tx(
+        &mut self,
+        tx: &MultiEraTx,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for crdt in self.tx_commands(tx, point) {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                self.process_tx(&tx, &point, output)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::PoolByStake(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reducers::fixtures::{babbage_tx, decode_tx, hash28};
+
+    fn reducer() -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: Some("pools".to_string()),
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    /// Delegation of a key credential to a pool, then deregistration of a
+    /// script credential
+    fn certs() -> String {
+        let delegation = format!("83 02 8200{} {}", hash28(0x11), hash28(0x44));
+        let deregistration = format!("82 01 8201{}", hash28(0x55));
+
+        format!("82 {} {}", delegation, deregistration)
+    }
+
+    #[test]
+    fn tracks_delegations_and_deregistrations() {
+        let cbor = babbage_tx(Some(&certs()), true, None);
+        let tx = decode_tx(&cbor);
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        let commands = reducer().tx_commands(&tx, &point);
+
+        assert_eq!(commands.len(), 2);
+
+        match &commands[0] {
+            CRDTCommand::AnyWriteWins(key, model::Value::String(pool), _) => {
+                assert_eq!(key, &format!("pools.{}", hex::encode([0x11; 28])));
+                assert_eq!(pool, &hex::encode([0x44; 28]));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+
+        match &commands[1] {
+            CRDTCommand::KeyRemove(key, _) => {
+                assert_eq!(key, &format!("pools.{}", hex::encode([0x55; 28])));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skips_invalid_txs() {
+        let cbor = babbage_tx(Some(&certs()), false, None);
+        let tx = decode_tx(&cbor);
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        assert!(reducer().tx_commands(&tx, &point).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use pallas::codec::utils::Nullable;
+use pallas::ledger::primitives::alonzo::{Certificate, Relay};
+use pallas::ledger::traverse::{MultiEraBlock, MultiEraTx};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    chain: crosscut::ChainWellKnownInfo,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+fn nullable<T: Clone>(value: &Nullable<T>) -> Option<T> {
+    match value {
+        Nullable::Some(x) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+fn relay_to_json(relay: &Relay) -> serde_json::Value {
+    match relay {
+        Relay::SingleHostAddr(port, ipv4, ipv6) => {
+            let ipv4 = nullable(ipv4)
+                .and_then(|x| <[u8; 4]>::try_from(x.as_slice()).ok())
+                .map(|x| Ipv4Addr::from(x).to_string());
+
+            let ipv6 = nullable(ipv6)
+                .and_then(|x| <[u8; 16]>::try_from(x.as_slice()).ok())
+                .map(|x| Ipv6Addr::from(x).to_string());
+
+            json!({ "port": nullable(port), "ipv4": ipv4, "ipv6": ipv6 })
+        }
+        Relay::SingleHostName(port, dns) => json!({ "port": nullable(port), "dns": dns }),
+        Relay::MultiHostName(dns) => json!({ "srv": dns }),
+    }
+}
+
+impl Reducer {
+    fn key(&self, pool_hex: &str) -> String {
+        match &self.config.key_prefix {
+            Some(prefix) => format!("{}.{}", prefix, pool_hex),
+            None => pool_hex.to_string(),
+        }
+    }
+
+    fn tx_commands(&self, tx: &MultiEraTx, epoch: u64, point: &Point) -> Vec<CRDTCommand> {
+        let mut commands = vec![];
+
+        if !tx.is_valid() {
+            return commands;
+        }
+
+        for cert in tx.certs() {
+            match cert.as_alonzo() {
+                Some(Certificate::PoolRegistration {
+                    operator,
+                    vrf_keyhash,
+                    pledge,
+                    cost,
+                    margin,
+                    reward_account,
+                    pool_owners,
+                    relays,
+                    pool_metadata,
+                }) => {
+                    let key = self.key(&hex::encode(operator));
+
+                    let metadata = pool_metadata.as_ref().map(|x| {
+                        json!({
+                            "url": x.url,
+                            "hash": hex::encode(x.hash),
+                        })
+                    });
+
+                    let value = json!({
+                        "epoch": epoch,
+                        "slot": point.slot_or_default(),
+                        "vrf_keyhash": hex::encode(vrf_keyhash),
+                        "pledge": pledge,
+                        "cost": cost,
+                        "margin": {
+                            "numerator": margin.numerator,
+                            "denominator": margin.denominator,
+                        },
+                        "reward_account": hex::encode(reward_account.as_slice()),
+                        "owners": pool_owners.iter().map(hex::encode).collect::<Vec<_>>(),
+                        "relays": relays.iter().map(relay_to_json).collect::<Vec<_>>(),
+                        "metadata": metadata,
+                    });
+
+                    commands.push(CRDTCommand::AnyWriteWins(
+                        key.clone(),
+                        value.into(),
+                        point.clone(),
+                    ));
+
+                    // registering again cancels any pending retirement
+                    commands.push(CRDTCommand::KeyRemove(
+                        format!("{}.retirement", key),
+                        point.clone(),
+                    ));
+                }
+                Some(Certificate::PoolRetirement(pool, retiring_epoch)) => {
+                    let key = format!("{}.retirement", self.key(&hex::encode(pool)));
+
+                    let value = json!({
+                        "epoch": retiring_epoch,
+                        "announced_epoch": epoch,
+                        "slot": point.slot_or_default(),
+                    });
+
+                    commands.push(CRDTCommand::AnyWriteWins(key, value.into(), point.clone()));
+                }
+                _ => (),
+            }
+        }
+
+        commands
+    }
+
+    fn process_tx(
+        &mut self,
+        tx: &MultiEraTx,
+        epoch: u64,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for crdt in self.tx_commands(tx, epoch, point) {
+            output.send(gasket::messaging::Message::from(crdt))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+        let epoch = crosscut::epochs::block_epoch(&self.chain, block);
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                self.process_tx(&tx, epoch, &point, output)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(
+        self,
+        chain: &crosscut::ChainWellKnownInfo,
+        policy: &crosscut::policies::RuntimePolicy,
+    ) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            chain: chain.clone(),
+            policy: policy.clone(),
+        };
+
+        super::Reducer::PoolMetadata(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reducers::fixtures::{babbage_tx, decode_tx, hash28};
+
+    const EPOCH: u64 = 250;
+
+    fn reducer() -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: Some("pools".to_string()),
+                filter: None,
+            },
+            chain: crosscut::ChainWellKnownInfo::mainnet(),
+            policy: Default::default(),
+        }
+    }
+
+    fn registration() -> String {
+        let vrf = format!("5820{}", "77".repeat(32));
+        let margin = "d81e 82 01 1864";
+        let reward_account = format!("581de1{}", "88".repeat(28));
+        let owners = format!("81 {}", hash28(0x99));
+        let relay = "84 00 190bb9 447f000001 f6";
+        let metadata = format!(
+            "82 6c{} 5820{}",
+            hex::encode("https://x.io"),
+            "bb".repeat(32)
+        );
+
+        format!(
+            "8a 03 {} {} 1a05f5e100 1a1443fd00 {} {} {} 81 {} {}",
+            hash28(0x66),
+            vrf,
+            margin,
+            reward_account,
+            owners,
+            relay,
+            metadata
+        )
+    }
+
+    fn retirement() -> String {
+        format!("83 04 {} 19012c", hash28(0x66))
+    }
+
+    fn commands(certs: &[String]) -> Vec<CRDTCommand> {
+        let certs = format!("{:02x} {}", 0x80 + certs.len(), certs.join(" "));
+        let cbor = babbage_tx(Some(&certs), true, None);
+
+        let point = Point::Specific(100, vec![0xff; 32]);
+        reducer().tx_commands(&decode_tx(&cbor), EPOCH, &point)
+    }
+
+    fn key() -> String {
+        format!("pools.{}", hex::encode([0x66; 28]))
+    }
+
+    #[test]
+    fn stores_registrations() {
+        let commands = commands(&[registration()]);
+        assert_eq!(commands.len(), 2);
+
+        match &commands[0] {
+            CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _) => {
+                assert_eq!(key, &self::key());
+                assert_eq!(value["epoch"], EPOCH);
+                assert_eq!(value["pledge"], 100000000);
+                assert_eq!(
+                    value["margin"],
+                    json!({ "numerator": 1, "denominator": 100 })
+                );
+                assert_eq!(value["owners"], json!([hex::encode([0x99; 28])]));
+                assert_eq!(
+                    value["relays"],
+                    json!([{ "port": 3001, "ipv4": "127.0.0.1", "ipv6": null }])
+                );
+                assert_eq!(value["metadata"]["url"], "https://x.io");
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stores_retirement_epoch() {
+        let commands = commands(&[retirement()]);
+        assert_eq!(commands.len(), 1);
+
+        match &commands[0] {
+            CRDTCommand::AnyWriteWins(key, model::Value::Json(value), _) => {
+                assert_eq!(key, &format!("{}.retirement", self::key()));
+                assert_eq!(
+                    value,
+                    &json!({ "epoch": 300, "announced_epoch": EPOCH, "slot": 100 })
+                );
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn registering_again_cancels_retirement() {
+        let commands = commands(&[retirement(), registration()]);
+        assert_eq!(commands.len(), 3);
+
+        match &commands[2] {
+            CRDTCommand::KeyRemove(key, _) => {
+                assert_eq!(key, &format!("{}.retirement", self::key()));
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+}
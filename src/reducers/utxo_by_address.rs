@@ -0,0 +1,216 @@
+use pallas::ledger::addresses::Address;
+use pallas::ledger::traverse::MultiEraOutput;
+use pallas::ledger::traverse::{MultiEraBlock, OutputRef};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressKey {
+    /// full address in bech32 (base58 for Byron)
+    Bech32,
+    /// hex of the payment credential, groups addresses with different stake
+    /// parts. Byron addresses fallback to base58.
+    PaymentCredential,
+}
+
+impl Default for AddressKey {
+    fn default() -> Self {
+        AddressKey::Bech32
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    pub address_key: Option<AddressKey>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+impl Reducer {
+    fn set_key(&self, output: &MultiEraOutput) -> Result<String, crate::Error> {
+        let address = output.address().map_err(crate::Error::ledger)?;
+
+        let key = match (self.config.address_key.unwrap_or_default(), &address) {
+            (AddressKey::PaymentCredential, Address::Shelley(x)) => x.payment().to_hex(),
+            _ => address.to_string(),
+        };
+
+        match &self.config.key_prefix {
+            Some(prefix) => Ok(format!("{}.{}", prefix, key)),
+            None => Ok(key),
+        }
+    }
+
+    fn process_consumed_txo(
+        &mut self,
+        ctx: &model::BlockContext,
+        input: &OutputRef,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let utxo = ctx.find_utxo(input).apply_policy(&self.policy).or_panic()?;
+
+        let utxo = match utxo {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let crdt = self.consumed_command(input, &utxo, point).or_panic()?;
+
+        output.send(gasket::messaging::Message::from(crdt))
+    }
+
+    /// The spent output leaves the set of its address
+    fn consumed_command(
+        &self,
+        input: &OutputRef,
+        utxo: &MultiEraOutput,
+        point: &Point,
+    ) -> Result<CRDTCommand, crate::Error> {
+        let key = self.set_key(utxo)?;
+
+        Ok(CRDTCommand::SetRemove(
+            key,
+            input.to_string(),
+            point.clone(),
+        ))
+    }
+
+    fn process_produced_txo(
+        &mut self,
+        utxo_ref: &OutputRef,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let crdt = self
+            .produced_command(utxo_ref, tx_output, point)
+            .or_panic()?;
+
+        output.send(gasket::messaging::Message::from(crdt))
+    }
+
+    /// The new output joins the set of its address
+    fn produced_command(
+        &self,
+        utxo_ref: &OutputRef,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+    ) -> Result<CRDTCommand, crate::Error> {
+        let key = self.set_key(tx_output)?;
+
+        Ok(CRDTCommand::SetAdd(
+            key,
+            utxo_ref.to_string(),
+            point.clone(),
+        ))
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                for consumed in tx.consumes().iter().map(|i| i.output_ref()) {
+                    self.process_consumed_txo(ctx, &consumed, &point, output)?;
+                }
+
+                for (idx, produced) in tx.produces() {
+                    let utxo_ref = OutputRef::new(tx.hash(), idx as u64);
+                    self.process_produced_txo(&utxo_ref, &produced, &point, output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::UtxoByAddress(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::crypto::hash::Hash;
+
+    use super::*;
+    use crate::reducers::fixtures::{babbage_output, decode_output, BASE_ADDRESS};
+
+    fn reducer(address_key: Option<AddressKey>) -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: Some("utxos".to_string()),
+                address_key,
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    fn point() -> Point {
+        Point::Specific(100, vec![0xff; 32])
+    }
+
+    #[test]
+    fn produced_outputs_join_the_set() {
+        let cbor = babbage_output(BASE_ADDRESS, 1000000, &[], None);
+        let output = decode_output(&cbor);
+
+        let utxo_ref = OutputRef::new(Hash::new([0xaa; 32]), 1);
+        let crdt = reducer(None)
+            .produced_command(&utxo_ref, &output, &point())
+            .unwrap();
+
+        match crdt {
+            CRDTCommand::SetAdd(key, member, point) => {
+                assert_eq!(key, format!("utxos.{}", BASE_ADDRESS));
+                assert_eq!(member, utxo_ref.to_string());
+                assert_eq!(point.slot_or_default(), 100);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn consumed_outputs_leave_the_set() {
+        let cbor = babbage_output(BASE_ADDRESS, 1000000, &[], None);
+        let output = decode_output(&cbor);
+
+        let input = OutputRef::new(Hash::new([0xbb; 32]), 0);
+        let crdt = reducer(Some(AddressKey::PaymentCredential))
+            .consumed_command(&input, &output, &point())
+            .unwrap();
+
+        // keyed by the payment credential, regardless of the stake part
+        match crdt {
+            CRDTCommand::SetRemove(key, member, point) => {
+                assert_eq!(key, format!("utxos.{}", hex::encode([0x11; 28])));
+                assert_eq!(member, input.to_string());
+                assert_eq!(point.slot_or_default(), 100);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+}
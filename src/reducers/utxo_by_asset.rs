@@ -0,0 +1,180 @@
+use pallas::ledger::traverse::MultiEraOutput;
+use pallas::ledger::traverse::{Asset, MultiEraBlock, OutputRef};
+use pallas::network::miniprotocols::Point;
+use serde::Deserialize;
+
+use crate::model::CRDTCommand;
+use crate::{crosscut, model, prelude::*};
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub key_prefix: Option<String>,
+    /// hex of the policies to track, every policy if absent
+    pub policy_ids: Option<Vec<String>>,
+    pub filter: Option<crosscut::filters::Predicate>,
+}
+
+pub struct Reducer {
+    config: Config,
+    policy: crosscut::policies::RuntimePolicy,
+}
+
+impl Reducer {
+    /// Keys of the sets (`policy.asset_name` in hex) that the output belongs to
+    fn set_keys(&self, output: &MultiEraOutput) -> Vec<String> {
+        let mut keys: Vec<_> = output
+            .non_ada_assets()
+            .into_iter()
+            .filter_map(|asset| match asset {
+                Asset::Ada(_) => None,
+                Asset::NativeAsset(policy, name, _) => {
+                    Some((hex::encode(policy), hex::encode(name)))
+                }
+            })
+            .filter(|(policy, _)| match &self.config.policy_ids {
+                Some(allowed) => allowed.contains(policy),
+                None => true,
+            })
+            .map(|(policy, name)| match &self.config.key_prefix {
+                Some(prefix) => format!("{}.{}.{}", prefix, policy, name),
+                None => format!("{}.{}", policy, name),
+            })
+            .collect();
+
+        keys.sort();
+        keys.dedup();
+
+        keys
+    }
+
+    fn process_consumed_txo(
+        &mut self,
+        ctx: &model::BlockContext,
+        input: &OutputRef,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let utxo = ctx.find_utxo(input).apply_policy(&self.policy).or_panic()?;
+
+        let utxo = match utxo {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        for key in self.set_keys(&utxo) {
+            output.send(gasket::messaging::Message::from(CRDTCommand::SetRemove(
+                key,
+                input.to_string(),
+                point.clone(),
+            )))?;
+        }
+
+        Ok(())
+    }
+
+    fn process_produced_txo(
+        &mut self,
+        utxo_ref: &OutputRef,
+        tx_output: &MultiEraOutput,
+        point: &Point,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        for key in self.set_keys(tx_output) {
+            output.send(gasket::messaging::Message::from(CRDTCommand::SetAdd(
+                key,
+                utxo_ref.to_string(),
+                point.clone(),
+            )))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reduce_block<'b>(
+        &mut self,
+        block: &'b MultiEraBlock<'b>,
+        ctx: &model::BlockContext,
+        output: &mut super::OutputPort,
+    ) -> Result<(), gasket::error::Error> {
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        for tx in block.txs().into_iter() {
+            if filter_matches!(self, block, &tx, ctx) {
+                for consumed in tx.consumes().iter().map(|i| i.output_ref()) {
+                    self.process_consumed_txo(ctx, &consumed, &point, output)?;
+                }
+
+                for (idx, produced) in tx.produces() {
+                    let utxo_ref = OutputRef::new(tx.hash(), idx as u64);
+                    self.process_produced_txo(&utxo_ref, &produced, &point, output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn plugin(self, policy: &crosscut::policies::RuntimePolicy) -> super::Reducer {
+        let reducer = Reducer {
+            config: self,
+            policy: policy.clone(),
+        };
+
+        super::Reducer::UtxoByAsset(reducer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::crypto::hash::Hash;
+
+    use super::*;
+    use crate::reducers::fixtures::{babbage_output, decode_output, ENTERPRISE_ADDRESS};
+
+    fn reducer(key_prefix: Option<&str>, policy_ids: Option<Vec<String>>) -> Reducer {
+        Reducer {
+            config: Config {
+                key_prefix: key_prefix.map(String::from),
+                policy_ids,
+                filter: None,
+            },
+            policy: Default::default(),
+        }
+    }
+
+    #[test]
+    fn keys_by_policy_and_name() {
+        let first = Hash::<28>::new([0x11; 28]);
+        let second = Hash::<28>::new([0x22; 28]);
+
+        // the same asset listed twice only belongs to its set once
+        let assets = [
+            (second, &b"B"[..], 1),
+            (first, &b"A"[..], 1),
+            (second, &b"B"[..], 2),
+        ];
+
+        let cbor = babbage_output(ENTERPRISE_ADDRESS, 1000000, &assets, None);
+        let output = decode_output(&cbor);
+
+        assert_eq!(
+            reducer(None, None).set_keys(&output),
+            vec![format!("{}.41", first), format!("{}.42", second)]
+        );
+
+        assert_eq!(
+            reducer(Some("assets"), Some(vec![second.to_string()])).set_keys(&output),
+            vec![format!("assets.{}.42", second)]
+        );
+    }
+
+    #[test]
+    fn ada_only_outputs_have_no_keys() {
+        let cbor = babbage_output(ENTERPRISE_ADDRESS, 1000000, &[], None);
+        let output = decode_output(&cbor);
+
+        assert!(reducer(None, None).set_keys(&output).is_empty());
+    }
+}
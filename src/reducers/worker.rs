@@ -37,7 +37,7 @@ impl Worker {
     fn reduce_block<'b>(
         &mut self,
         block: &'b [u8],
-        ctx: &model::BlockContext,
+        mut ctx: model::BlockContext,
     ) -> Result<(), gasket::error::Error> {
         let block = MultiEraBlock::decode(block)
             .map_err(crate::Error::cbor)
@@ -51,12 +51,15 @@ impl Worker {
 
         self.last_block.set(block.number() as i64);
 
+        // indexed once for every reducer that resolves datums by hash
+        ctx.import_witness_datums(&block);
+
         self.output.send(gasket::messaging::Message::from(
             model::CRDTCommand::block_starting(&block),
         ))?;
 
         for reducer in self.reducers.iter_mut() {
-            reducer.reduce_block(&block, ctx, &mut self.output)?;
+            reducer.reduce_block(&block, &ctx, &mut self.output)?;
             self.ops_count.inc(1);
         }
 
@@ -89,7 +92,7 @@ impl gasket::runtime::Worker for Worker {
 
         match msg.payload {
             model::EnrichedBlockPayload::RollForward(block, ctx) => {
-                self.reduce_block(&block, &ctx)?
+                self.reduce_block(&block, ctx)?
             }
             model::EnrichedBlockPayload::RollBack(point) => {
                 log::warn!("rollback requested for {:?}", point);
@@ -0,0 +1,284 @@
+use gasket::error::AsWorkError;
+use pallas::codec::Fragment;
+use pallas::network::miniprotocols::{chainsync, Point};
+use pallas::network::multiplexer::StdChannel;
+
+use crate::prelude::*;
+use crate::{crosscut, model, sources::utils, storage, Error};
+
+pub type OutputPort = gasket::messaging::OutputPort<model::RawBlockPayload>;
+
+pub type Client<C> = chainsync::Client<StdChannel, C>;
+
+/// Default number of storage cursor points to try when looking for an
+/// intersection with the chain
+pub const DEFAULT_CURSOR_DEPTH: usize = 10;
+
+/// Strategy that defines the content received through chain-sync
+///
+/// N2N chain-sync provides block headers that need to be complemented with a
+/// block-fetch round-trip, while N2C chain-sync provides full blocks right
+/// away. Everything else in the chain-sync flow is shared by both transports
+/// and lives in the generic [Worker].
+pub trait ContentStrategy {
+    type Content: Fragment;
+
+    /// Whether the contents are full blocks, too heavy to skim the whole chain
+    /// through when looking for an intersection relative to the tip
+    const FULL_BLOCKS: bool;
+
+    /// Connects to the upstream node and returns a chain-sync client
+    fn connect(
+        &mut self,
+        chain: &crosscut::ChainWellKnownInfo,
+    ) -> Result<Client<Self::Content>, Error>;
+
+    /// Extracts the chain point described by a chain-sync content
+    fn point_of(content: &Self::Content) -> Result<Point, Error>;
+
+    /// Holds a received content until its point reaches the required depth
+    fn retain(&mut self, point: Point, content: Self::Content);
+
+    /// Retrieves the full block cbor of a point that reached the required depth
+    fn fetch_block(&mut self, point: &Point) -> Result<Vec<u8>, gasket::error::Error>;
+}
+
+pub struct Worker<S>
+where
+    S: ContentStrategy,
+{
+    strategy: S,
+    min_depth: usize,
+    policy: crosscut::policies::RuntimePolicy,
+    chain_buffer: chainsync::RollbackBuffer,
+    chain: crosscut::ChainWellKnownInfo,
+    intersect: crosscut::IntersectConfig,
+    cursor: storage::Cursor,
+    cursor_depth: usize,
+    cursor_recovery: crosscut::CursorRecovery,
+    finalize: Option<crosscut::FinalizeConfig>,
+    chainsync: Option<Client<S::Content>>,
+    output: OutputPort,
+    processed: u64,
+    reached_tip: bool,
+    block_count: gasket::metrics::Counter,
+    chain_tip: gasket::metrics::Gauge,
+}
+
+impl<S> Worker<S>
+where
+    S: ContentStrategy,
+{
+    pub fn new(
+        strategy: S,
+        min_depth: usize,
+        policy: crosscut::policies::RuntimePolicy,
+        chain: crosscut::ChainWellKnownInfo,
+        intersect: crosscut::IntersectConfig,
+        finalize: Option<crosscut::FinalizeConfig>,
+        cursor: storage::Cursor,
+        cursor_depth: usize,
+        cursor_recovery: crosscut::CursorRecovery,
+        output: OutputPort,
+    ) -> Self {
+        Self {
+            strategy,
+            min_depth,
+            policy,
+            chain,
+            intersect,
+            finalize,
+            cursor,
+            cursor_depth,
+            cursor_recovery,
+            output,
+            chainsync: None,
+            processed: 0,
+            reached_tip: false,
+            block_count: Default::default(),
+            chain_tip: Default::default(),
+            chain_buffer: chainsync::RollbackBuffer::new(),
+        }
+    }
+
+    fn on_roll_forward(&mut self, content: S::Content) -> Result<(), gasket::error::Error> {
+        // parse the content and extract the point of the chain
+        let point = S::point_of(&content)
+            .apply_policy(&self.policy)
+            .or_panic()?;
+
+        let point = match point {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        // keep the content around until the point is confirmed
+        self.strategy.retain(point.clone(), content);
+
+        // track the new point in our memory buffer
+        log::debug!("rolling forward to point {:?}", point);
+        self.chain_buffer.roll_forward(point);
+
+        Ok(())
+    }
+
+    fn on_rollback(&mut self, point: &Point) -> Result<(), gasket::error::Error> {
+        log::debug!("rolling block to point {:?}", point);
+
+        match self.chain_buffer.roll_back(point) {
+            chainsync::RollbackEffect::Handled => {
+                log::debug!("handled rollback within buffer {:?}", point);
+            }
+            chainsync::RollbackEffect::OutOfScope => {
+                log::debug!("rollback out of buffer scope, sending event down the pipeline");
+                self.output
+                    .send(model::RawBlockPayload::roll_back(point.clone()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_next(
+        &mut self,
+        next: chainsync::NextResponse<S::Content>,
+    ) -> Result<(), gasket::error::Error> {
+        match next {
+            chainsync::NextResponse::RollForward(c, t) => {
+                self.on_roll_forward(c)?;
+                self.chain_tip.set(t.1 as i64);
+                self.reached_tip = false;
+                Ok(())
+            }
+            chainsync::NextResponse::RollBackward(p, t) => {
+                self.on_rollback(&p)?;
+                self.chain_tip.set(t.1 as i64);
+                Ok(())
+            }
+            chainsync::NextResponse::Await => {
+                log::info!("chain-sync reached the tip of the chain");
+                self.reached_tip = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn request_next(&mut self) -> Result<(), gasket::error::Error> {
+        log::info!("requesting next block");
+
+        let next = self
+            .chainsync
+            .as_mut()
+            .unwrap()
+            .request_next()
+            .or_restart()?;
+
+        self.on_next(next)
+    }
+
+    fn await_next(&mut self) -> Result<(), gasket::error::Error> {
+        log::info!("awaiting next block (blocking)");
+
+        let next = self
+            .chainsync
+            .as_mut()
+            .unwrap()
+            .recv_while_must_reply()
+            .or_restart()?;
+
+        match next {
+            chainsync::NextResponse::Await => {
+                unreachable!("protocol invariant not respected in chain-sync state machine")
+            }
+            _ => self.on_next(next),
+        }
+    }
+}
+
+impl<S> gasket::runtime::Worker for Worker<S>
+where
+    S: ContentStrategy + Send,
+    S::Content: Send,
+{
+    fn metrics(&self) -> gasket::metrics::Registry {
+        gasket::metrics::Builder::new()
+            .with_counter("received_blocks", &self.block_count)
+            .with_gauge("chain_tip", &self.chain_tip)
+            .build()
+    }
+
+    fn bootstrap(&mut self) -> Result<(), gasket::error::Error> {
+        if S::FULL_BLOCKS && matches!(self.intersect, crosscut::IntersectConfig::TipMinus(_)) {
+            return Err(Error::config(
+                "TipMinus intersect skims the whole chain, it's only available through N2N",
+            ))
+            .or_panic();
+        }
+
+        let mut chainsync = self.strategy.connect(&self.chain).or_retry()?;
+
+        let start = utils::define_chainsync_start(
+            &self.intersect,
+            &self.chain,
+            &mut self.cursor,
+            self.cursor_depth,
+            &self.cursor_recovery,
+            &mut chainsync,
+            S::point_of,
+        );
+
+        let start = match start {
+            Ok(x) => x,
+            Err(err @ Error::IntersectNotFound) => return Err(err).or_panic(),
+            Err(err @ Error::ConfigError(_)) => return Err(err).or_panic(),
+            Err(err) => return Err(err).or_retry(),
+        };
+
+        let start = start.ok_or(Error::IntersectNotFound).or_panic()?;
+
+        log::info!("chain-sync intersection is {:?}", start);
+
+        if let utils::ChainSyncStart::Rollback(point) = start {
+            log::warn!("rolling back pipeline to cursor point {:?}", point);
+            self.output.send(model::RawBlockPayload::roll_back(point))?;
+        }
+
+        self.chainsync = Some(chainsync);
+
+        Ok(())
+    }
+
+    fn work(&mut self) -> gasket::runtime::WorkResult {
+        match self.chainsync.as_ref().unwrap().has_agency() {
+            true => self.request_next()?,
+            false => self.await_next()?,
+        };
+
+        // see if we have points that already reached certain depth
+        let ready = self.chain_buffer.pop_with_depth(self.min_depth);
+        log::debug!("found {} points with required min depth", ready.len());
+
+        // retrieve the block for each confirmed point and send down the pipeline
+        for point in ready {
+            let block = self.strategy.fetch_block(&point)?;
+
+            self.output
+                .send(model::RawBlockPayload::roll_forward(block))?;
+
+            self.block_count.inc(1);
+            self.processed += 1;
+
+            // evaluate if we should finalize the thread according to config
+            if crosscut::should_finalize(&self.finalize, &self.chain, &point, self.processed) {
+                return Ok(gasket::runtime::WorkOutcome::Done);
+            }
+        }
+
+        if self.reached_tip && crosscut::should_finalize_at_tip(&self.finalize) {
+            log::info!("finalizing chain-sync after reaching the tip");
+            return Ok(gasket::runtime::WorkOutcome::Done);
+        }
+
+        Ok(gasket::runtime::WorkOutcome::Partial)
+    }
+}
@@ -6,6 +6,7 @@ use crate::{bootstrap, crosscut, model, storage};
 #[cfg(target_family = "unix")]
 pub mod n2c;
 
+pub mod chainsync;
 pub mod n2n;
 pub mod utils;
 
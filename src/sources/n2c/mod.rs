@@ -4,6 +4,7 @@ mod transport;
 use serde::Deserialize;
 use std::time::Duration;
 
+use crate::sources::chainsync::DEFAULT_CURSOR_DEPTH;
 use crate::{bootstrap, crosscut, model, storage};
 
 use gasket::messaging::OutputPort;
@@ -12,6 +13,8 @@ use gasket::messaging::OutputPort;
 pub struct Config {
     pub path: String,
     pub min_depth: Option<usize>,
+    pub cursor_depth: Option<usize>,
+    pub cursor_recovery: Option<crosscut::CursorRecovery>,
 }
 
 impl Config {
@@ -50,13 +53,15 @@ impl Bootstrapper {
     pub fn spawn_stages(self, pipeline: &mut bootstrap::Pipeline, cursor: storage::Cursor) {
         pipeline.register_stage(gasket::runtime::spawn_stage(
             self::chainsync::Worker::new(
-                self.config.path.clone(),
+                self::chainsync::BlockStrategy::new(self.config.path.clone()),
                 self.config.min_depth.unwrap_or(0),
                 self.policy,
                 self.chain,
                 self.intersect,
                 self.finalize,
                 cursor,
+                self.config.cursor_depth.unwrap_or(DEFAULT_CURSOR_DEPTH),
+                self.config.cursor_recovery.unwrap_or_default(),
                 self.output,
             ),
             gasket::runtime::Policy {
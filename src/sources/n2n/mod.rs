@@ -8,6 +8,7 @@ use gasket::messaging::OutputPort;
 use pallas::network::miniprotocols::Point;
 use serde::Deserialize;
 
+use crate::sources::chainsync::DEFAULT_CURSOR_DEPTH;
 use crate::{bootstrap, crosscut, model, storage};
 
 #[derive(Clone, Debug)]
@@ -34,6 +35,8 @@ impl ChainSyncInternalPayload {
 pub struct Config {
     pub address: String,
     pub min_depth: Option<usize>,
+    pub cursor_depth: Option<usize>,
+    pub cursor_recovery: Option<crosscut::CursorRecovery>,
 }
 
 impl Config {
@@ -72,13 +75,15 @@ impl Bootstrapper {
     pub fn spawn_stages(self, pipeline: &mut bootstrap::Pipeline, cursor: storage::Cursor) {
         pipeline.register_stage(gasket::runtime::spawn_stage(
             self::chainsync::Worker::new(
-                self.config.address.clone(),
+                self::chainsync::HeaderStrategy::new(self.config.address.clone()),
                 self.config.min_depth.unwrap_or(0),
                 self.policy,
                 self.chain.clone(),
                 self.intersect,
                 self.finalize,
                 cursor,
+                self.config.cursor_depth.unwrap_or(DEFAULT_CURSOR_DEPTH),
+                self.config.cursor_recovery.unwrap_or_default(),
                 self.output,
             ),
             gasket::runtime::Policy {
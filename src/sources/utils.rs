@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::convert::TryInto;
 
 use pallas::{
@@ -10,23 +11,241 @@ use pallas::{
 
 use crate::{crosscut, storage};
 
-pub fn define_chainsync_start<C: Fragment>(
-    intersect: &crosscut::IntersectConfig,
+/// Extra points kept while skimming so that a rollback doesn't leave fewer
+/// than the requested amount behind, matches the security parameter (k) of
+/// mainnet which bounds the depth of rollbacks
+const SKIM_ROLLBACK_MARGIN: usize = 2160;
+
+/// Messages of the chain-sync reader that matter while skimming
+enum SkimStep {
+    /// a new point, along with the tip of the chain
+    Forward(Point, Point),
+    Backward(Point),
+    Await,
+}
+
+/// Where skimming stopped, along with the point `depth` blocks behind the last
+/// one skimmed (or the oldest one if there aren't enough)
+#[derive(Debug, PartialEq)]
+enum Skimmed {
+    /// right before a point that satisfies the condition
+    Reached(Point),
+    /// right after reaching the tip of the chain
+    Tip(Point),
+}
+
+/// Keeps track of the most recent `depth + 1` points reported by `next`, plus
+/// a margin to survive rollbacks. Stops right before a point that satisfies
+/// `reached` or right after reaching the tip of the chain.
+fn skim(
+    mut next: impl FnMut() -> Result<SkimStep, crate::Error>,
+    depth: usize,
+    reached: impl Fn(&Point) -> bool,
+) -> Result<Skimmed, crate::Error> {
+    let mut history = VecDeque::<Point>::new();
+    let mut at_tip = false;
+
+    loop {
+        match next()? {
+            SkimStep::Forward(point, tip) => {
+                if reached(&point) {
+                    break;
+                }
+
+                log::debug!("skimming over point {:?}", point);
+                history.push_back(point.clone());
+
+                if history.len() > depth + 1 + SKIM_ROLLBACK_MARGIN {
+                    history.pop_front();
+                }
+
+                if point == tip {
+                    at_tip = true;
+                    break;
+                }
+            }
+            SkimStep::Backward(point) => {
+                while let Some(last) = history.back() {
+                    if last == &point {
+                        break;
+                    }
+
+                    history.pop_back();
+                }
+
+                if history.is_empty() {
+                    history.push_back(point);
+                }
+            }
+            SkimStep::Await => {
+                log::info!("chain-sync reached the tip of the chain while skimming");
+            }
+        }
+    }
+
+    let index = history.len().saturating_sub(depth + 1);
+
+    let point = history
+        .remove(index)
+        .ok_or_else(|| crate::Error::message("no points found while skimming the chain"))?;
+
+    match at_tip {
+        true => Ok(Skimmed::Tip(point)),
+        false => Ok(Skimmed::Reached(point)),
+    }
+}
+
+/// Moves the chain-sync reader forward from the current intersection without
+/// processing any block, see [skim] for the point returned
+fn skim_chain<C: Fragment>(
+    client: &mut chainsync::Client<StdChannel, C>,
+    point_of: fn(&C) -> Result<Point, crate::Error>,
+    depth: usize,
+    reached: impl Fn(&Point) -> bool,
+) -> Result<Skimmed, crate::Error> {
+    let next = || -> Result<SkimStep, crate::Error> {
+        let next = match client.has_agency() {
+            true => client.request_next(),
+            false => client.recv_while_must_reply(),
+        }
+        .map_err(crate::Error::ouroboros)?;
+
+        match next {
+            chainsync::NextResponse::RollForward(content, tip) => {
+                Ok(SkimStep::Forward(point_of(&content)?, tip.0))
+            }
+            chainsync::NextResponse::RollBackward(point, _) => Ok(SkimStep::Backward(point)),
+            chainsync::NextResponse::Await => Ok(SkimStep::Await),
+        }
+    };
+
+    skim(next, depth, reached)
+}
+
+fn intersect_skimming<C: Fragment>(
+    anchors: Vec<Point>,
+    client: &mut chainsync::Client<StdChannel, C>,
+    point_of: fn(&C) -> Result<Point, crate::Error>,
+    depth: usize,
+    reached: impl Fn(&Point) -> bool,
+) -> Result<Option<Skimmed>, crate::Error> {
+    let (anchor, _) = client
+        .find_intersect(anchors)
+        .map_err(crate::Error::ouroboros)?;
+
+    let anchor = match anchor {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    log::info!("skimming chain from well-known point {:?}", anchor);
+    let skimmed = skim_chain(client, point_of, depth, reached)?;
+
+    let target = match &skimmed {
+        Skimmed::Reached(x) | Skimmed::Tip(x) => x.clone(),
+    };
+
+    let (point, _) = client
+        .find_intersect(vec![target])
+        .map_err(crate::Error::ouroboros)?;
+
+    Ok(point.map(|_| skimmed))
+}
+
+/// Point where chain-sync will start from
+#[derive(Debug)]
+pub enum ChainSyncStart {
+    /// continue from the intersection point
+    Continue(Point),
+    /// continue from the intersection point, which is older than the last
+    /// point processed by storage, rolling back the pipeline first
+    Rollback(Point),
+}
+
+fn intersect_cursor<C: Fragment>(
     cursor: &mut storage::Cursor,
+    cursor_depth: usize,
+    recovery: &crosscut::CursorRecovery,
     client: &mut chainsync::Client<StdChannel, C>,
-) -> Result<Option<Point>, crate::Error> {
-    match cursor.last_point()? {
-        Some(x) => {
-            log::info!("found existing cursor in storage plugin: {:?}", x);
-            let point = x.try_into()?;
-            let (point, _) = client
-                .find_intersect(vec![point])
-                .map_err(crate::Error::ouroboros)?;
-            return Ok(point);
+) -> Result<Option<ChainSyncStart>, crate::Error> {
+    let points = cursor
+        .last_points(cursor_depth.max(1))?
+        .into_iter()
+        .map(|x| x.try_into())
+        .collect::<Result<Vec<Point>, _>>()?;
+
+    let latest = match points.first() {
+        Some(x) => x.clone(),
+        None => {
+            log::info!("no cursor found in storage plugin");
+            return Ok(None);
         }
-        None => log::info!("no cursor found in storage plugin"),
     };
 
+    log::info!("found existing cursor in storage plugin: {:?}", latest);
+
+    let (point, _) = client
+        .find_intersect(points.clone())
+        .map_err(crate::Error::ouroboros)?;
+
+    match point {
+        Some(x) if x == latest => Ok(Some(ChainSyncStart::Continue(x))),
+        Some(x) => match recovery {
+            crosscut::CursorRecovery::Rollback => {
+                log::warn!(
+                    "cursor {:?} is no longer on chain, rolling back to {:?}",
+                    latest,
+                    x
+                );
+
+                Ok(Some(ChainSyncStart::Rollback(x)))
+            }
+            crosscut::CursorRecovery::Fail => {
+                log::error!(
+                    "cursor {:?} is no longer on chain, last valid cursor point is {:?}",
+                    latest,
+                    x
+                );
+
+                Err(crate::Error::IntersectNotFound)
+            }
+        },
+        None => {
+            log::error!(
+                "none of the last {} cursor points are on chain: {:?}",
+                points.len(),
+                points
+            );
+
+            Err(crate::Error::IntersectNotFound)
+        }
+    }
+}
+
+pub fn define_chainsync_start<C: Fragment>(
+    intersect: &crosscut::IntersectConfig,
+    chain: &crosscut::ChainWellKnownInfo,
+    cursor: &mut storage::Cursor,
+    cursor_depth: usize,
+    recovery: &crosscut::CursorRecovery,
+    client: &mut chainsync::Client<StdChannel, C>,
+    point_of: fn(&C) -> Result<Point, crate::Error>,
+) -> Result<Option<ChainSyncStart>, crate::Error> {
+    if let Some(start) = intersect_cursor(cursor, cursor_depth, recovery, client)? {
+        return Ok(Some(start));
+    }
+
+    let point = define_intersect_start(intersect, chain, client, point_of)?;
+
+    Ok(point.map(ChainSyncStart::Continue))
+}
+
+fn define_intersect_start<C: Fragment>(
+    intersect: &crosscut::IntersectConfig,
+    chain: &crosscut::ChainWellKnownInfo,
+    client: &mut chainsync::Client<StdChannel, C>,
+    point_of: fn(&C) -> Result<Point, crate::Error>,
+) -> Result<Option<Point>, crate::Error> {
     match &intersect {
         crosscut::IntersectConfig::Origin => {
             let point = client.intersect_origin().map_err(crate::Error::ouroboros)?;
@@ -50,5 +269,115 @@ pub fn define_chainsync_start<C: Fragment>(
                 .map_err(crate::Error::ouroboros)?;
             Ok(point)
         }
+        crosscut::IntersectConfig::Epoch(_) | crosscut::IntersectConfig::Timestamp(_) => {
+            let target = intersect.get_target_slot(chain).expect("target slot");
+
+            let mut anchors: Vec<_> = chain
+                .known_points()
+                .into_iter()
+                .filter(|p| p.slot_or_default() < target)
+                .collect();
+
+            anchors.push(Point::Origin);
+
+            let skimmed = intersect_skimming(anchors, client, point_of, 0, |p| {
+                p.slot_or_default() >= target
+            })?;
+
+            match skimmed {
+                Some(Skimmed::Reached(x)) => Ok(Some(x)),
+                Some(Skimmed::Tip(_)) => Err(crate::Error::config(format!(
+                    "intersect slot {} is beyond the tip of the chain",
+                    target
+                ))),
+                None => Ok(None),
+            }
+        }
+        crosscut::IntersectConfig::TipMinus(blocks) => {
+            let mut anchors = chain.known_points();
+            anchors.push(Point::Origin);
+
+            let skimmed =
+                intersect_skimming(anchors, client, point_of, *blocks as usize, |_| false)?;
+
+            Ok(skimmed.map(|x| match x {
+                Skimmed::Reached(x) | Skimmed::Tip(x) => x,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(slot: u64) -> Point {
+        Point::Specific(slot, vec![slot as u8; 32])
+    }
+
+    fn fork(slot: u64) -> Point {
+        Point::Specific(slot, vec![0xff; 32])
+    }
+
+    /// Replays the given messages of the chain-sync reader
+    fn replay(steps: Vec<SkimStep>) -> impl FnMut() -> Result<SkimStep, crate::Error> {
+        let mut steps = steps.into_iter();
+        move || Ok(steps.next().expect("skimmed past the scripted steps"))
+    }
+
+    #[test]
+    fn tip_minus_survives_rollbacks() {
+        let tip = fork(11);
+
+        let mut steps = vec![SkimStep::Backward(Point::Origin)];
+        steps.extend((1..=10).map(|x| SkimStep::Forward(point(x), tip.clone())));
+
+        // blocks 9 and 10 are rolled back and replaced by the fork at 11
+        steps.push(SkimStep::Backward(point(8)));
+        steps.push(SkimStep::Forward(fork(11), tip.clone()));
+
+        // 3 blocks behind the tip: 7, 8 and 11 come after 6
+        assert_eq!(
+            skim(replay(steps), 3, |_| false).unwrap(),
+            Skimmed::Tip(point(6))
+        );
+    }
+
+    #[test]
+    fn tip_minus_of_a_short_chain() {
+        let steps = vec![
+            SkimStep::Backward(Point::Origin),
+            SkimStep::Forward(point(1), point(2)),
+            SkimStep::Forward(point(2), point(2)),
+        ];
+
+        assert_eq!(
+            skim(replay(steps), 5, |_| false).unwrap(),
+            Skimmed::Tip(Point::Origin)
+        );
+    }
+
+    #[test]
+    fn stops_right_before_target() {
+        let tip = point(10);
+
+        let steps = (1..=10)
+            .map(|x| SkimStep::Forward(point(x), tip.clone()))
+            .collect();
+
+        let skimmed = skim(replay(steps), 0, |p| p.slot_or_default() >= 5).unwrap();
+        assert_eq!(skimmed, Skimmed::Reached(point(4)));
+    }
+
+    #[test]
+    fn reports_target_beyond_tip() {
+        let tip = point(10);
+
+        let steps = (1..=10)
+            .map(|x| SkimStep::Forward(point(x), tip.clone()))
+            .collect();
+
+        let skimmed = skim(replay(steps), 0, |p| p.slot_or_default() >= 50).unwrap();
+        assert_eq!(skimmed, Skimmed::Tip(point(10)));
     }
 }
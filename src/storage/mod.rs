@@ -71,4 +71,13 @@ impl Cursor {
             Cursor::Postgres(x) => x.last_point(),
         }
     }
+
+    /// Returns up to `count` of the most recent points processed by the
+    /// storage, ordered from newest to oldest
+    pub fn last_points(&mut self, count: usize) -> Result<Vec<PointArg>, crate::Error> {
+        match self {
+            Cursor::Skip(x) => x.last_points(count),
+            Cursor::Postgres(x) => x.last_points(count),
+        }
+    }
 }
@@ -74,8 +74,7 @@ pub struct Cursor {
 }
 
 impl Cursor {
-    fn rows_to_point(res: Vec<postgres::Row>) -> Option<crosscut::PointArg> {
-        let row: &postgres::Row = res.get(0)?;
+    fn row_to_point(row: &postgres::Row) -> Option<crosscut::PointArg> {
         let slot: i64 = row.get(0);
         let hash: String = row.get(1);
         let s = format!("{},{}", slot, hash);
@@ -83,6 +82,11 @@ impl Cursor {
     }
 
     pub fn last_point(&mut self) -> Result<Option<crosscut::PointArg>, crate::Error> {
+        let points = self.last_points(1)?;
+        Ok(points.into_iter().next())
+    }
+
+    pub fn last_points(&mut self, count: usize) -> Result<Vec<crosscut::PointArg>, crate::Error> {
         let mut connection = postgres::Client::connect(
             self.config.connection_params.clone().as_str(),
             postgres::NoTls,
@@ -91,15 +95,232 @@ impl Cursor {
 
         let raw = connection
             .query(
-                "SELECT slot, hash FROM cursor ORDER BY slot DESC LIMIT 1",
-                &[],
+                "SELECT slot, hash FROM cursor ORDER BY slot DESC LIMIT $1",
+                &[&(count as i64)],
             )
             .map_err(crate::Error::storage)?;
 
-        let point = Self::rows_to_point(raw);
+        let points = raw.iter().filter_map(Self::row_to_point).collect();
+
+        Ok(points)
+    }
+}
+
+fn value_to_text(value: model::Value) -> String {
+    match value {
+        model::Value::String(x) => x,
+        model::Value::BigInt(x) => x.to_string(),
+        model::Value::Cbor(x) => hex::encode(x),
+        model::Value::Json(x) => x.to_string(),
+    }
+}
+
+/// Tables backing the CRDT structures, rows written by a block reference its
+/// cursor so that they're dropped when the block is rolled back
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS cursor (
+        slot   BIGINT NOT NULL,
+        hash   TEXT NOT NULL,
+        PRIMARY KEY (slot)
+    );
+
+    CREATE TABLE IF NOT EXISTS voting_power (
+        id           SERIAL PRIMARY KEY,
+        spending     TEXT NOT NULL,
+        staking      TEXT NOT NULL,
+        policy       TEXT NOT NULL,
+        token        TEXT NOT NULL,
+        amount       BIGINT NOT NULL,
+        created_slot BIGINT NOT NULL REFERENCES cursor ON DELETE CASCADE,
+        tx_id        TEXT NOT NULL,
+        tx_idx       BIGINT NOT NULL,
+        spent_slot   BIGINT
+    );
+
+    CREATE INDEX IF NOT EXISTS voting_power_spending_idx ON voting_power (spending);
+    CREATE INDEX IF NOT EXISTS voting_power_staking_idx ON voting_power (staking);
+    CREATE INDEX IF NOT EXISTS voting_power_policy_idx ON voting_power (policy);
+    CREATE INDEX IF NOT EXISTS voting_power_token_idx ON voting_power (token);
+    CREATE INDEX IF NOT EXISTS voting_power_utxo_idx ON voting_power (tx_id, tx_idx);
+
+    CREATE TABLE IF NOT EXISTS set_members (
+        id           SERIAL PRIMARY KEY,
+        set_key      TEXT NOT NULL,
+        member       TEXT NOT NULL,
+        created_slot BIGINT NOT NULL REFERENCES cursor ON DELETE CASCADE,
+        removed_slot BIGINT
+    );
+
+    CREATE INDEX IF NOT EXISTS set_members_key_idx ON set_members (set_key);
+    CREATE INDEX IF NOT EXISTS set_members_member_idx ON set_members (member);
+
+    CREATE TABLE IF NOT EXISTS counter_deltas (
+        id           SERIAL PRIMARY KEY,
+        counter_key  TEXT NOT NULL,
+        delta        BIGINT NOT NULL,
+        slot         BIGINT NOT NULL REFERENCES cursor ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS counter_deltas_key_idx ON counter_deltas (counter_key);
+
+    CREATE TABLE IF NOT EXISTS key_values (
+        id           SERIAL PRIMARY KEY,
+        value_key    TEXT NOT NULL,
+        value        TEXT,
+        slot         BIGINT NOT NULL REFERENCES cursor ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS key_values_key_idx ON key_values (value_key, slot DESC, id DESC);
+";
+
+fn create_schema(client: &mut postgres::Client) -> Result<(), crate::Error> {
+    client.batch_execute(SCHEMA).map_err(crate::Error::storage)
+}
+
+fn apply_command(
+    client: &mut postgres::Client,
+    command: model::CRDTCommand,
+) -> Result<(), crate::Error> {
+    match command {
+        model::CRDTCommand::BlockStarting(Point::Specific(slot, hash)) => {
+            log::debug!("block started {:?}", slot);
+            let hash_str = hex::encode(hash);
+            client
+                .execute(
+                    "INSERT INTO cursor (slot, hash) VALUES ($1, $2)",
+                    &[&(slot as i64), &hash_str],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::BlockStarting(Point::Origin) => {}
+        model::CRDTCommand::BlockFinished(_) => {}
+        model::CRDTCommand::VotingPowerCreated {
+            owner,
+            policy,
+            name,
+            amount,
+            point: Point::Specific(slot, _hash),
+            tx_id,
+            tx_idx,
+        } => {
+            let spending = owner.payment().to_hex();
+            let staking = owner.delegation().to_hex();
+
+            client
+                .execute("INSERT INTO voting_power (spending, staking, policy, token, amount, created_slot, tx_id, tx_idx, spent_slot) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NULL)"
+                         , &[&spending,
+				 &staking,
+				 &policy,
+				 &name,
+				 &(amount as i64),
+				 &(slot as i64),
+				 &tx_id,
+				 &(tx_idx as i64)
+			     ])
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::VotingPowerCreated {
+            point: Point::Origin,
+            ..
+        } => unreachable!(),
+        model::CRDTCommand::VotingPowerSpent {
+            tx_id,
+            tx_idx,
+            point,
+        } => {
+            let slot = match point {
+                Point::Specific(slot, _) => slot,
+                Point::Origin => 0,
+            };
+
+            client
+                .execute(
+                    "UPDATE voting_power SET spent_slot = $1 WHERE tx_id = $2 AND tx_idx = $3",
+                    &[&(slot as i64), &tx_id, &(tx_idx as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::SetAdd(set, member, point) => {
+            let slot = point.slot_or_default();
+
+            client
+                .execute(
+                    "INSERT INTO set_members (set_key, member, created_slot, removed_slot) VALUES ($1, $2, $3, NULL)",
+                    &[&set, &member, &(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::SetRemove(set, member, point) => {
+            let slot = point.slot_or_default();
+
+            client
+                .execute(
+                    "UPDATE set_members SET removed_slot = $1 WHERE set_key = $2 AND member = $3 AND removed_slot IS NULL",
+                    &[&(slot as i64), &set, &member],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::PNCounter(key, delta, point) => {
+            let slot = point.slot_or_default();
+
+            // the value of a counter is the sum of its deltas, rows of
+            // rolled back blocks are dropped along with their cursor
+            client
+                .execute(
+                    "INSERT INTO counter_deltas (counter_key, delta, slot) VALUES ($1, $2, $3)",
+                    &[&key, &delta, &(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::AnyWriteWins(key, value, point) => {
+            let slot = point.slot_or_default();
+            let value = value_to_text(value);
+
+            // the current value of a key is the one of its latest row, a
+            // NULL value means the key was removed
+            client
+                .execute(
+                    "INSERT INTO key_values (value_key, value, slot) VALUES ($1, $2, $3)",
+                    &[&key, &value, &(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::KeyRemove(key, point) => {
+            let slot = point.slot_or_default();
+
+            client
+                .execute(
+                    "INSERT INTO key_values (value_key, value, slot) VALUES ($1, NULL, $2)",
+                    &[&key, &(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
+        model::CRDTCommand::RollBack(point) => {
+            let slot = match point {
+                Point::Specific(slot, _) => slot,
+                Point::Origin => 0,
+            };
+            client
+                .execute("DELETE FROM cursor WHERE slot > $1", &[&(slot as i64)])
+                .map_err(crate::Error::storage)?;
 
-        Ok(point)
+            client
+                .execute(
+                    "UPDATE voting_power SET spent_slot = NULL WHERE spent_slot > $1",
+                    &[&(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+
+            client
+                .execute(
+                    "UPDATE set_members SET removed_slot = NULL WHERE removed_slot > $1",
+                    &[&(slot as i64)],
+                )
+                .map_err(crate::Error::storage)?;
+        }
     }
+
+    Ok(())
 }
 
 pub struct Worker {
@@ -124,132 +345,93 @@ impl gasket::runtime::Worker for Worker {
         .or_panic()?;
         self.connection = Some(connection);
 
-        self.connection
-            .as_mut()
-            .unwrap()
-            .batch_execute(
-                "
-                CREATE TABLE IF NOT EXISTS cursor (
-                    slot   BIGINT NOT NULL,
-                    hash   TEXT NOT NULL,
-                    PRIMARY KEY (slot)
-                );
-
-                CREATE TABLE IF NOT EXISTS voting_power (
-                    id           SERIAL PRIMARY KEY,
-                    spending     TEXT NOT NULL,
-                    staking      TEXT NOT NULL,
-                    policy       TEXT NOT NULL,
-                    token        TEXT NOT NULL,
-                    amount       BIGINT NOT NULL,
-                    created_slot BIGINT NOT NULL REFERENCES cursor ON DELETE CASCADE,
-                    tx_id        TEXT NOT NULL,
-                    tx_idx       BIGINT NOT NULL,
-                    spent_slot   BIGINT
-                );
-
-                CREATE INDEX IF NOT EXISTS voting_power_spending_idx ON voting_power (spending);
-                CREATE INDEX IF NOT EXISTS voting_power_staking_idx ON voting_power (staking);
-                CREATE INDEX IF NOT EXISTS voting_power_policy_idx ON voting_power (policy);
-                CREATE INDEX IF NOT EXISTS voting_power_token_idx ON voting_power (token);
-                CREATE INDEX IF NOT EXISTS voting_power_utxo_idx ON voting_power (tx_id, tx_idx);
-            ",
-            )
-            .or_panic()?;
+        create_schema(self.connection.as_mut().unwrap()).or_panic()?;
 
         Ok(())
     }
 
     fn work(&mut self) -> gasket::runtime::WorkResult {
         let msg = self.input.recv_or_idle()?;
-        match msg.payload {
-            model::CRDTCommand::BlockStarting(Point::Specific(slot, hash)) => {
-                log::debug!("block started {:?}", slot);
-                let hash_str = hex::encode(hash);
-                self.connection
-                    .as_mut()
-                    .unwrap()
-                    .execute(
-                        "INSERT INTO cursor (slot, hash) VALUES ($1, $2)",
-                        &[&(slot as i64), &hash_str],
-                    )
-                    .or_panic()?;
-            }
-            model::CRDTCommand::BlockStarting(Point::Origin) => {}
-            model::CRDTCommand::BlockFinished(_) => {}
-            model::CRDTCommand::VotingPowerCreated {
-                owner,
-                policy,
-                name,
-                amount,
-                point: Point::Specific(slot, _hash),
-                tx_id,
-                tx_idx,
-            } => {
-                let spending = owner.payment().to_hex();
-                let staking = owner.delegation().to_hex();
-
-                self.connection
-                    .as_mut()
-                    .unwrap()
-                    .execute("INSERT INTO voting_power (spending, staking, policy, token, amount, created_slot, tx_id, tx_idx, spent_slot) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NULL)"
-                             , &[&spending,
-				 &staking,
-				 &policy,
-				 &name,
-				 &(amount as i64),
-				 &(slot as i64),
-				 &tx_id,
-				 &(tx_idx as i64)
-			     ])
-                    .or_panic()?;
-            }
-            model::CRDTCommand::VotingPowerCreated {
-                point: Point::Origin,
-                ..
-            } => unreachable!(),
-            model::CRDTCommand::VotingPowerSpent {
-                tx_id,
-                tx_idx,
-                point,
-            } => {
-                let slot = match point {
-                    Point::Specific(slot, _) => slot,
-                    Point::Origin => 0,
-                };
-
-                self.connection
-                    .as_mut()
-                    .unwrap()
-                    .execute(
-                        "UPDATE voting_power SET spent_slot = $1 WHERE tx_id = $2 AND tx_idx = $3",
-                        &[&(slot as i64), &tx_id, &(tx_idx as i64)],
-                    )
-                    .or_panic()?;
-            }
-            model::CRDTCommand::RollBack(point) => {
-                let slot = match point {
-                    Point::Specific(slot, _) => slot,
-                    Point::Origin => 0,
-                };
-                self.connection
-                    .as_mut()
-                    .unwrap()
-                    .execute("DELETE FROM cursor WHERE slot > $1", &[&(slot as i64)])
-                    .or_panic()?;
-
-                self.connection
-                    .as_mut()
-                    .unwrap()
-                    .execute(
-                        "UPDATE voting_power SET spent_slot = NULL WHERE spent_slot > $1",
-                        &[&(slot as i64)],
-                    )
-                    .or_panic()?;
-            }
-        };
+        apply_command(self.connection.as_mut().unwrap(), msg.payload).or_panic()?;
+
         self.ops_count.inc(1);
         self.input.commit();
         Ok(WorkOutcome::Partial)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::CRDTCommand;
+
+    /// Connection params of a scratch database for the ignored tests
+    const TEST_DB_VAR: &str = "SCROLLS_TEST_POSTGRES";
+
+    fn point(slot: u64) -> Point {
+        Point::Specific(slot, vec![slot as u8; 32])
+    }
+
+    fn members(client: &mut postgres::Client, set: &str) -> Vec<(String, Option<i64>)> {
+        client
+            .query(
+                "SELECT member, removed_slot FROM set_members WHERE set_key = $1 ORDER BY member",
+                &[&set],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect()
+    }
+
+    // needs a live database, run with:
+    // SCROLLS_TEST_POSTGRES="host=localhost user=postgres" cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn rollback_restores_set_members() {
+        let params = std::env::var(TEST_DB_VAR)
+            .unwrap_or_else(|_| panic!("{} must hold the connection params", TEST_DB_VAR));
+
+        let mut client = postgres::Client::connect(&params, postgres::NoTls).unwrap();
+
+        // a schema of its own, so that runs don't see each other's rows
+        let schema = format!("scrolls_test_{}", std::process::id());
+        client
+            .batch_execute(&format!(
+                "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}; SET search_path TO {0}",
+                schema
+            ))
+            .unwrap();
+
+        create_schema(&mut client).unwrap();
+
+        let commands = vec![
+            CRDTCommand::BlockStarting(point(10)),
+            CRDTCommand::SetAdd("addr".into(), "tx#0".into(), point(10)),
+            CRDTCommand::BlockStarting(point(20)),
+            CRDTCommand::SetRemove("addr".into(), "tx#0".into(), point(20)),
+            CRDTCommand::SetAdd("addr".into(), "tx#1".into(), point(20)),
+        ];
+
+        for command in commands {
+            apply_command(&mut client, command).unwrap();
+        }
+
+        assert_eq!(
+            members(&mut client, "addr"),
+            vec![("tx#0".to_string(), Some(20)), ("tx#1".to_string(), None)]
+        );
+
+        // members removed after the point are back, the ones added are gone
+        apply_command(&mut client, CRDTCommand::RollBack(point(10))).unwrap();
+
+        assert_eq!(
+            members(&mut client, "addr"),
+            vec![("tx#0".to_string(), None)]
+        );
+
+        client
+            .batch_execute(&format!("DROP SCHEMA {} CASCADE", schema))
+            .unwrap();
+    }
+}
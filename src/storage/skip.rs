@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -11,6 +12,11 @@ use crate::{bootstrap, crosscut, model};
 
 type InputPort = gasket::messaging::TwoPhaseInputPort<model::CRDTCommand>;
 
+/// Max number of processed points kept in memory to serve as cursor
+const MAX_CURSOR_POINTS: usize = 100;
+
+type PointHistory = Arc<Mutex<VecDeque<crosscut::PointArg>>>;
+
 #[derive(Deserialize, Clone)]
 pub struct Config {}
 
@@ -18,14 +24,14 @@ impl Config {
     pub fn bootstrapper(self) -> Bootstrapper {
         Bootstrapper {
             input: Default::default(),
-            last_point: Arc::new(Mutex::new(None)),
+            last_points: Arc::new(Mutex::new(VecDeque::new())),
         }
     }
 }
 
 pub struct Bootstrapper {
     input: InputPort,
-    last_point: Arc<Mutex<Option<crosscut::PointArg>>>,
+    last_points: PointHistory,
 }
 
 impl Bootstrapper {
@@ -35,7 +41,7 @@ impl Bootstrapper {
 
     pub fn build_cursor(&mut self) -> Cursor {
         Cursor {
-            last_point: self.last_point.clone(),
+            last_points: self.last_points.clone(),
         }
     }
 
@@ -43,7 +49,7 @@ impl Bootstrapper {
         let worker = Worker {
             input: self.input,
             ops_count: Default::default(),
-            last_point: self.last_point.clone(),
+            last_points: self.last_points.clone(),
         };
 
         pipeline.register_stage(spawn_stage(
@@ -58,20 +64,25 @@ impl Bootstrapper {
 }
 
 pub struct Cursor {
-    last_point: Arc<Mutex<Option<crosscut::PointArg>>>,
+    last_points: PointHistory,
 }
 
 impl Cursor {
     pub fn last_point(&self) -> Result<Option<crosscut::PointArg>, crate::Error> {
-        let value = self.last_point.lock().unwrap();
-        Ok(value.clone())
+        let value = self.last_points.lock().unwrap();
+        Ok(value.front().cloned())
+    }
+
+    pub fn last_points(&self, count: usize) -> Result<Vec<crosscut::PointArg>, crate::Error> {
+        let value = self.last_points.lock().unwrap();
+        Ok(value.iter().take(count).cloned().collect())
     }
 }
 
 pub struct Worker {
     ops_count: gasket::metrics::Counter,
     input: InputPort,
-    last_point: Arc<Mutex<Option<crosscut::PointArg>>>,
+    last_points: PointHistory,
 }
 
 impl gasket::runtime::Worker for Worker {
@@ -107,13 +118,35 @@ impl gasket::runtime::Worker for Worker {
             } => {
                 log::debug!("removing voting power [{tx_id}#{tx_idx}] at [{point:?}]",);
             }
+            model::CRDTCommand::SetAdd(set, member, point) => {
+                log::debug!("adding to set [{set}] value [{member}] at [{point:?}]");
+            }
+            model::CRDTCommand::SetRemove(set, member, point) => {
+                log::debug!("removing from set [{set}] value [{member}] at [{point:?}]");
+            }
+            model::CRDTCommand::PNCounter(key, delta, point) => {
+                log::debug!("adding [{delta}] to counter [{key}] at [{point:?}]");
+            }
+            model::CRDTCommand::AnyWriteWins(key, value, point) => {
+                log::debug!("setting [{key}] to [{value:?}] at [{point:?}]");
+            }
+            model::CRDTCommand::KeyRemove(key, point) => {
+                log::debug!("removing [{key}] at [{point:?}]");
+            }
             model::CRDTCommand::BlockFinished(point) => {
                 log::debug!("block finished {:?}", point);
-                let mut last_point = self.last_point.lock().unwrap();
-                *last_point = Some(crosscut::PointArg::from(point));
+                let mut last_points = self.last_points.lock().unwrap();
+                last_points.push_front(crosscut::PointArg::from(point));
+                last_points.truncate(MAX_CURSOR_POINTS);
             }
             model::CRDTCommand::RollBack(point) => {
                 log::debug!("rollback to {:?}", point);
+                let slot = point.slot_or_default();
+                let mut last_points = self.last_points.lock().unwrap();
+                last_points.retain(|x| match x {
+                    crosscut::PointArg::Origin => true,
+                    crosscut::PointArg::Specific(s, _) => *s <= slot,
+                });
             }
         };
 
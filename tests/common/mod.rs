@@ -0,0 +1,488 @@
+//! A stand-in Ouroboros node used to exercise the pipeline end-to-end without a
+//! real Cardano node. It speaks the handshake, chain-sync and block-fetch
+//! mini-protocols (responder side) over TCP or Unix sockets, serving blocks
+//! derived from the `assets/test.block` fixture and following a scripted
+//! sequence of roll-forward / roll-back steps.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::thread;
+
+use pallas::codec::minicbor::{data::Tag, Decoder, Encoder};
+use pallas::ledger::traverse::MultiEraBlock;
+use pallas::network::miniprotocols::Point;
+
+const MAX_SEGMENT_PAYLOAD: usize = 12288;
+const RESPONDER_FLAG: u16 = 0x8000;
+
+const PROTOCOL_HANDSHAKE: u16 = 0;
+const PROTOCOL_N2N_CHAINSYNC: u16 = 2;
+const PROTOCOL_BLOCKFETCH: u16 = 3;
+const PROTOCOL_N2C_CHAINSYNC: u16 = 5;
+
+pub fn test_block_cbor() -> Vec<u8> {
+    hex::decode(include_str!("../../assets/test.block")).unwrap()
+}
+
+/// A block served by the mock node
+#[derive(Clone, Debug)]
+pub struct FixtureBlock {
+    pub point: Point,
+    pub number: u64,
+    pub era: u16,
+    pub header: Vec<u8>,
+    pub cbor: Vec<u8>,
+}
+
+impl FixtureBlock {
+    /// Derives a block from the test fixture by re-stamping the number and
+    /// slot of its header, which also yields a distinct block hash. The rest
+    /// of the block (including the prev-hash) is left untouched.
+    pub fn derive(number: u64, slot: u64) -> Self {
+        let original = test_block_cbor();
+
+        // wrapper -> block -> header -> header body
+        let mut d = Decoder::new(&original);
+        d.array().unwrap();
+        d.u16().unwrap();
+        d.array().unwrap();
+        d.array().unwrap();
+        d.array().unwrap();
+
+        let start = d.position();
+        d.u64().unwrap();
+        d.u64().unwrap();
+        let end = d.position();
+
+        let mut fields = Encoder::new(Vec::new());
+        fields.u64(number).unwrap().u64(slot).unwrap();
+        let fields = fields.into_writer();
+
+        let cbor = [&original[..start], &fields[..], &original[end..]].concat();
+
+        let mut d = Decoder::new(&cbor);
+        d.array().unwrap();
+        let era = d.u16().unwrap();
+        d.array().unwrap();
+        let header_start = d.position();
+        d.skip().unwrap();
+        let header_end = d.position();
+        let header = cbor[header_start..header_end].to_vec();
+
+        let block = MultiEraBlock::decode(&cbor).unwrap();
+        assert_eq!(block.slot(), slot);
+        assert_eq!(block.number(), number);
+
+        let point = Point::Specific(block.slot(), block.hash().to_vec());
+
+        Self {
+            point,
+            number,
+            era,
+            header,
+            cbor,
+        }
+    }
+
+    /// Builds a sequence of consecutive blocks starting at the given number
+    pub fn sequence(first_number: u64, count: u64, first_slot: u64) -> Vec<Self> {
+        (0..count)
+            .map(|i| Self::derive(first_number + i, first_slot + i * 20))
+            .collect()
+    }
+
+    /// The hard-fork combinator era index used in N2N header variants
+    fn header_variant(&self) -> u8 {
+        (self.era - 1) as u8
+    }
+}
+
+/// A scripted chain-sync event
+#[derive(Clone, Debug)]
+pub enum Step {
+    RollForward(FixtureBlock),
+    RollBack(Point),
+}
+
+/// The sequence of events served by the mock node
+#[derive(Clone, Debug, Default)]
+pub struct MockChain {
+    pub steps: Vec<Step>,
+}
+
+impl MockChain {
+    pub fn from_blocks(blocks: Vec<FixtureBlock>) -> Self {
+        Self {
+            steps: blocks.into_iter().map(Step::RollForward).collect(),
+        }
+    }
+
+    pub fn roll_forward(mut self, blocks: Vec<FixtureBlock>) -> Self {
+        self.steps
+            .extend(blocks.into_iter().map(Step::RollForward));
+        self
+    }
+
+    pub fn roll_back(mut self, point: Point) -> Self {
+        self.steps.push(Step::RollBack(point));
+        self
+    }
+
+    fn blocks(&self) -> impl Iterator<Item = (usize, &FixtureBlock)> {
+        self.steps.iter().enumerate().filter_map(|(i, s)| match s {
+            Step::RollForward(b) => Some((i, b)),
+            _ => None,
+        })
+    }
+
+    fn tip(&self) -> (Point, u64) {
+        self.blocks()
+            .last()
+            .map(|(_, b)| (b.point.clone(), b.number))
+            .unwrap_or((Point::Origin, 0))
+    }
+
+    fn find_block(&self, point: &Point) -> Option<&FixtureBlock> {
+        self.blocks().map(|(_, b)| b).find(|b| &b.point == point)
+    }
+
+    /// Returns the first requested point known by the chain and the index of
+    /// the step that follows it
+    fn find_intersect(&self, points: &[Point]) -> Option<(Point, usize)> {
+        for point in points {
+            if let Point::Origin = point {
+                return Some((Point::Origin, 0));
+            }
+
+            if let Some((idx, _)) = self.blocks().find(|(_, b)| &b.point == point) {
+                return Some((point.clone(), idx + 1));
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Flavor {
+    N2N,
+    N2C,
+}
+
+impl Flavor {
+    fn chainsync_protocol(&self) -> u16 {
+        match self {
+            Flavor::N2N => PROTOCOL_N2N_CHAINSYNC,
+            Flavor::N2C => PROTOCOL_N2C_CHAINSYNC,
+        }
+    }
+}
+
+fn encode_point(e: &mut Encoder<Vec<u8>>, point: &Point) {
+    match point {
+        Point::Origin => {
+            e.array(0).unwrap();
+        }
+        Point::Specific(slot, hash) => {
+            e.array(2).unwrap().u64(*slot).unwrap().bytes(hash).unwrap();
+        }
+    }
+}
+
+fn decode_point(d: &mut Decoder) -> Point {
+    match d.array().unwrap() {
+        Some(0) => Point::Origin,
+        _ => {
+            let slot = d.u64().unwrap();
+            let hash = d.bytes().unwrap().to_vec();
+            Point::Specific(slot, hash)
+        }
+    }
+}
+
+fn encode_tip(e: &mut Encoder<Vec<u8>>, tip: &(Point, u64)) {
+    e.array(2).unwrap();
+    encode_point(e, &tip.0);
+    e.u64(tip.1).unwrap();
+}
+
+/// Attempts to find a complete cbor message at the start of the buffer
+fn complete_message_len(buffer: &[u8]) -> Option<usize> {
+    let mut d = Decoder::new(buffer);
+
+    match d.skip() {
+        Ok(_) => Some(d.position()),
+        Err(e) if e.is_end_of_input() => None,
+        Err(e) => panic!("mock node received invalid cbor: {}", e),
+    }
+}
+
+struct Session<S> {
+    stream: S,
+    flavor: Flavor,
+    chain: Arc<MockChain>,
+    next_step: Option<usize>,
+    pending_rollback: Option<Point>,
+    buffers: HashMap<u16, Vec<u8>>,
+}
+
+impl<S> Session<S>
+where
+    S: Read + Write,
+{
+    fn send(&mut self, protocol: u16, payload: &[u8]) -> std::io::Result<()> {
+        for chunk in payload.chunks(MAX_SEGMENT_PAYLOAD) {
+            let mut segment = Vec::with_capacity(chunk.len() + 8);
+            segment.extend_from_slice(&0u32.to_be_bytes());
+            segment.extend_from_slice(&(protocol | RESPONDER_FLAG).to_be_bytes());
+            segment.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            segment.extend_from_slice(chunk);
+            self.stream.write_all(&segment)?;
+        }
+
+        self.stream.flush()
+    }
+
+    fn on_handshake(&mut self, msg: &[u8]) -> std::io::Result<()> {
+        let mut d = Decoder::new(msg);
+        d.array().unwrap();
+        assert_eq!(d.u8().unwrap(), 0, "expected handshake propose message");
+
+        let count = d.map().unwrap().unwrap();
+        let mut accepted: Option<(u64, &[u8])> = None;
+
+        for _ in 0..count {
+            let version = d.u64().unwrap();
+            let start = d.position();
+            d.skip().unwrap();
+            let data = &msg[start..d.position()];
+
+            if accepted.map(|(v, _)| version > v).unwrap_or(true) {
+                accepted = Some((version, data));
+            }
+        }
+
+        // accept the highest version echoing the version data of the client
+        let (version, data) = accepted.expect("at least one proposed version");
+
+        let mut e = Encoder::new(Vec::new());
+        e.array(3).unwrap().u8(1).unwrap().u64(version).unwrap();
+        let mut payload = e.into_writer();
+        payload.extend_from_slice(data);
+
+        self.send(PROTOCOL_HANDSHAKE, &payload)
+    }
+
+    fn on_chainsync(&mut self, msg: &[u8]) -> std::io::Result<()> {
+        let protocol = self.flavor.chainsync_protocol();
+        let tip = self.chain.tip();
+
+        let mut d = Decoder::new(msg);
+        d.array().unwrap();
+
+        let mut e = Encoder::new(Vec::new());
+
+        match d.u8().unwrap() {
+            // request next
+            0 => {
+                if let Some(point) = self.pending_rollback.take() {
+                    e.array(3).unwrap().u8(3).unwrap();
+                    encode_point(&mut e, &point);
+                    encode_tip(&mut e, &tip);
+                    return self.send(protocol, &e.into_writer());
+                }
+
+                let idx = self.next_step.expect("intersection before request next");
+                let chain = self.chain.clone();
+
+                match chain.steps.get(idx) {
+                    Some(Step::RollForward(block)) => {
+                        e.array(3).unwrap().u8(2).unwrap();
+
+                        match self.flavor {
+                            Flavor::N2N => {
+                                e.array(2).unwrap().u8(block.header_variant()).unwrap();
+                                e.tag(Tag::Cbor).unwrap().bytes(&block.header).unwrap();
+                            }
+                            Flavor::N2C => {
+                                e.tag(Tag::Cbor).unwrap().bytes(&block.cbor).unwrap();
+                            }
+                        }
+
+                        encode_tip(&mut e, &tip);
+                    }
+                    Some(Step::RollBack(point)) => {
+                        e.array(3).unwrap().u8(3).unwrap();
+                        encode_point(&mut e, point);
+                        encode_tip(&mut e, &tip);
+                    }
+                    None => {
+                        // nothing else to serve, the client will wait forever
+                        e.array(1).unwrap().u8(1).unwrap();
+                        return self.send(protocol, &e.into_writer());
+                    }
+                }
+
+                self.next_step = Some(idx + 1);
+                self.send(protocol, &e.into_writer())
+            }
+            // find intersect
+            4 => {
+                let count = d.array().unwrap().unwrap_or(0);
+                let points: Vec<_> = (0..count).map(|_| decode_point(&mut d)).collect();
+
+                match self.chain.find_intersect(&points) {
+                    Some((point, idx)) => {
+                        self.next_step = Some(idx);
+                        self.pending_rollback = Some(point.clone());
+
+                        e.array(3).unwrap().u8(5).unwrap();
+                        encode_point(&mut e, &point);
+                        encode_tip(&mut e, &tip);
+                    }
+                    None => {
+                        e.array(2).unwrap().u8(6).unwrap();
+                        encode_tip(&mut e, &tip);
+                    }
+                }
+
+                self.send(protocol, &e.into_writer())
+            }
+            // done
+            _ => Ok(()),
+        }
+    }
+
+    fn on_blockfetch(&mut self, msg: &[u8]) -> std::io::Result<()> {
+        let mut d = Decoder::new(msg);
+        d.array().unwrap();
+
+        // anything other than a range request is a client done message
+        if d.u8().unwrap() != 0 {
+            return Ok(());
+        }
+
+        let from = decode_point(&mut d);
+        let to = decode_point(&mut d);
+        assert_eq!(from, to, "mock node only serves single-block ranges");
+
+        let block = self.chain.find_block(&from).map(|b| b.cbor.clone());
+
+        let mut e = Encoder::new(Vec::new());
+
+        match block {
+            Some(cbor) => {
+                e.array(1).unwrap().u8(2).unwrap();
+                self.send(PROTOCOL_BLOCKFETCH, &e.into_writer())?;
+
+                let mut e = Encoder::new(Vec::new());
+                e.array(2).unwrap().u8(4).unwrap();
+                e.tag(Tag::Cbor).unwrap().bytes(&cbor).unwrap();
+                self.send(PROTOCOL_BLOCKFETCH, &e.into_writer())?;
+
+                let mut e = Encoder::new(Vec::new());
+                e.array(1).unwrap().u8(5).unwrap();
+                self.send(PROTOCOL_BLOCKFETCH, &e.into_writer())
+            }
+            None => {
+                e.array(1).unwrap().u8(3).unwrap();
+                self.send(PROTOCOL_BLOCKFETCH, &e.into_writer())
+            }
+        }
+    }
+
+    fn on_message(&mut self, protocol: u16, msg: &[u8]) -> std::io::Result<()> {
+        match protocol {
+            PROTOCOL_HANDSHAKE => self.on_handshake(msg),
+            PROTOCOL_BLOCKFETCH if self.flavor == Flavor::N2N => self.on_blockfetch(msg),
+            x if x == self.flavor.chainsync_protocol() => self.on_chainsync(msg),
+            x => panic!("mock node received message for unsupported protocol {}", x),
+        }
+    }
+
+    fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            let mut header = [0u8; 8];
+            self.stream.read_exact(&mut header)?;
+
+            let protocol = u16::from_be_bytes([header[4], header[5]]) & !RESPONDER_FLAG;
+            let length = u16::from_be_bytes([header[6], header[7]]) as usize;
+
+            let mut payload = vec![0u8; length];
+            self.stream.read_exact(&mut payload)?;
+
+            let buffer = self.buffers.entry(protocol).or_default();
+            buffer.extend(payload);
+
+            loop {
+                let buffer = self.buffers.get_mut(&protocol).unwrap();
+
+                let msg: Vec<u8> = match complete_message_len(buffer) {
+                    Some(len) => buffer.drain(..len).collect(),
+                    None => break,
+                };
+
+                self.on_message(protocol, &msg)?;
+            }
+        }
+    }
+}
+
+fn serve<S>(stream: S, flavor: Flavor, chain: Arc<MockChain>)
+where
+    S: Read + Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut session = Session {
+            stream,
+            flavor,
+            chain,
+            next_step: None,
+            pending_rollback: None,
+            buffers: HashMap::new(),
+        };
+
+        // the session ends when the client disconnects
+        let _ = session.run();
+    });
+}
+
+/// Starts a mock N2N node listening on a random loopback port, returns the
+/// address to connect to
+pub fn spawn_n2n(chain: MockChain) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let chain = Arc::new(chain);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            serve(stream, Flavor::N2N, chain.clone());
+        }
+    });
+
+    address
+}
+
+/// Starts a mock N2C node listening on a Unix socket, returns the socket path
+#[cfg(unix)]
+pub fn spawn_n2c(chain: MockChain, name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("scrolls-{}-{}.socket", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).unwrap();
+    let chain = Arc::new(chain);
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            serve(stream, Flavor::N2C, chain.clone());
+        }
+    });
+
+    path.to_string_lossy().to_string()
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use pallas::network::miniprotocols::Point;
+use scrolls::{bootstrap, crosscut, enrich, reducers, sources, storage};
+
+use common::{FixtureBlock, MockChain};
+
+fn run_until(
+    source: sources::Config,
+    intersect: crosscut::IntersectConfig,
+    expected_tip: &Point,
+) -> Vec<String> {
+    let chain = crosscut::ChainWellKnownInfo::mainnet();
+    let policy = crosscut::policies::RuntimePolicy::default();
+    let finalize = serde_json::from_value(serde_json::json!({ "until_tip": true })).unwrap();
+
+    let source = source.bootstrapper(&chain, &intersect, &Some(finalize), &policy);
+    let enrich = enrich::Config::Skip.bootstrapper(&intersect, &policy);
+
+    let reducer = reducers::Bootstrapper::new(
+        vec![reducers::Config::BalanceByAddress(
+            reducers::balance_by_address::Config {
+                save_ada: None,
+                aggregate_by: None,
+                filter: None,
+            },
+        )],
+        &chain,
+        &policy,
+    )
+    .unwrap();
+
+    let mut storage =
+        storage::Config::Skip(storage::skip::Config {}).plugin(&chain, &intersect, &policy);
+
+    let mut cursor = storage.build_cursor();
+
+    let pipeline = bootstrap::build(source, enrich, reducer, storage).unwrap();
+
+    let expected = crosscut::PointArg::from(expected_tip.clone()).to_string();
+    let deadline = Instant::now() + Duration::from_secs(30);
+
+    loop {
+        let last = cursor.last_point().unwrap().map(|x| x.to_string());
+
+        if last.as_ref() == Some(&expected) {
+            break;
+        }
+
+        assert!(
+            Instant::now() < deadline,
+            "pipeline didn't reach {} in time, last point is {:?}",
+            expected,
+            last
+        );
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let points = cursor
+        .last_points(100)
+        .unwrap()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+
+    for tether in pipeline.tethers {
+        let _ = tether.dismiss_stage();
+    }
+
+    points
+}
+
+fn points_of(blocks: &[&FixtureBlock]) -> Vec<String> {
+    blocks
+        .iter()
+        .map(|b| crosscut::PointArg::from(b.point.clone()).to_string())
+        .collect()
+}
+
+fn n2n_source(address: String) -> sources::Config {
+    sources::Config::N2N(sources::n2n::Config {
+        address,
+        min_depth: Some(0),
+        cursor_depth: None,
+        cursor_recovery: None,
+    })
+}
+
+#[test]
+fn n2n_syncs_from_origin() {
+    let blocks = FixtureBlock::sequence(1, 5, 1000);
+    let address = common::spawn_n2n(MockChain::from_blocks(blocks.clone()));
+
+    let points = run_until(
+        n2n_source(address),
+        crosscut::IntersectConfig::Origin,
+        &blocks[4].point,
+    );
+
+    let expected: Vec<_> = blocks.iter().rev().collect();
+    assert_eq!(points, points_of(&expected));
+}
+
+#[test]
+fn n2n_follows_rollbacks() {
+    let blocks = FixtureBlock::sequence(1, 4, 1000);
+    let fork = FixtureBlock::sequence(3, 3, 1041);
+
+    let chain = MockChain::from_blocks(blocks.clone())
+        .roll_back(blocks[1].point.clone())
+        .roll_forward(fork.clone());
+
+    let address = common::spawn_n2n(chain);
+
+    let points = run_until(
+        n2n_source(address),
+        crosscut::IntersectConfig::Origin,
+        &fork[2].point,
+    );
+
+    let expected = vec![&fork[2], &fork[1], &fork[0], &blocks[1], &blocks[0]];
+    assert_eq!(points, points_of(&expected));
+}
+
+#[test]
+fn n2n_intersects_tip_minus() {
+    let blocks = FixtureBlock::sequence(1, 5, 1000);
+    let address = common::spawn_n2n(MockChain::from_blocks(blocks.clone()));
+
+    let points = run_until(
+        n2n_source(address),
+        crosscut::IntersectConfig::TipMinus(2),
+        &blocks[4].point,
+    );
+
+    assert_eq!(points, points_of(&[&blocks[4], &blocks[3]]));
+}
+
+#[cfg(unix)]
+#[test]
+fn n2c_syncs_from_origin() {
+    let blocks = FixtureBlock::sequence(1, 5, 1000);
+    let path = common::spawn_n2c(MockChain::from_blocks(blocks.clone()), "n2c-origin");
+
+    let source = sources::Config::N2C(sources::n2c::Config {
+        path,
+        min_depth: Some(0),
+        cursor_depth: None,
+        cursor_recovery: None,
+    });
+
+    let points = run_until(source, crosscut::IntersectConfig::Origin, &blocks[4].point);
+
+    let expected: Vec<_> = blocks.iter().rev().collect();
+    assert_eq!(points, points_of(&expected));
+}
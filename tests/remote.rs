@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use pallas::crypto::hash::Hash;
+use pallas::ledger::traverse::{Era, MultiEraOutput};
+use scrolls::enrich::remote::Client;
+
+const TX_A: &str = "1e0a5b4bd6fe7a2ecd6f0b3b0dc84e4e6fc9d1f4c5ab3f7b48c25f3a4d7f0e11";
+const TX_B: &str = "9b2e3a6f2f58d3a0c5c4a7c1f0d9e8b7a6958473625140f3e2d1c0b9a8f7e6d5";
+const POLICY: &str = "a0028f350aaabe0545fdcb56b039bfb08e4bb4d8c4d7c3c7d481c235";
+const ADDRESS: &str = "addr1vyqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qavsj8u";
+const DATUM_HASH: &str = "923918e403bf43c34b4ef6b48eb2ee04babed17320d8d1b9ff9ad086e86f44ec";
+
+/// Starts a minimal HTTP server answering GET requests with the json body
+/// registered for the path, `null` for anything else
+fn spawn_stub(routes: HashMap<String, String>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line.split(' ').nth(1).unwrap_or("/").to_owned();
+
+            // drain the headers
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+            }
+
+            let body = routes.get(&path).cloned().unwrap_or_else(|| "null".into());
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    url
+}
+
+fn kupo_match(tx: &str, index: u64, value: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "transaction_index": 0,
+        "transaction_id": tx,
+        "output_index": index,
+        "address": ADDRESS,
+        "value": value,
+        "datum_hash": DATUM_HASH,
+        "datum_type": "hash",
+        "script_hash": null,
+        "created_at": { "slot_no": 1, "header_hash": TX_B },
+        "spent_at": null
+    })
+}
+
+fn stub_routes() -> HashMap<String, String> {
+    let mut routes = HashMap::new();
+
+    routes.insert(
+        format!("/matches/*@{}", TX_A),
+        serde_json::json!([
+            kupo_match(TX_A, 0, serde_json::json!({ "coins": 1500000 })),
+            kupo_match(
+                TX_A,
+                1,
+                serde_json::json!({
+                    "coins": 2000000,
+                    "assets": { (format!("{}.4d494c4b", POLICY)): 50, POLICY: 3 }
+                })
+            ),
+        ])
+        .to_string(),
+    );
+
+    routes.insert(
+        format!("/matches/*@{}", TX_B),
+        serde_json::json!([kupo_match(TX_B, 3, serde_json::json!({ "coins": 7 }))]).to_string(),
+    );
+
+    routes.insert(
+        format!("/datums/{}", DATUM_HASH),
+        serde_json::json!({ "datum": "d87980" }).to_string(),
+    );
+
+    routes
+}
+
+#[test]
+fn fetches_every_output_of_requested_txs() {
+    let url = spawn_stub(stub_routes());
+    let client = Client::new(&url, 2, Duration::from_secs(5)).unwrap();
+
+    let tx_a = Hash::<32>::from_str(TX_A).unwrap();
+    let tx_b = Hash::<32>::from_str(TX_B).unwrap();
+
+    let outputs = client.fetch_tx_outputs(&[tx_a, tx_b]).unwrap();
+
+    let outputs: HashMap<_, _> = outputs
+        .into_iter()
+        .map(|(utxo_ref, era, cbor)| (utxo_ref.to_string(), (era, cbor)))
+        .collect();
+
+    assert_eq!(outputs.len(), 3);
+
+    let (era, cbor) = &outputs[&format!("{}#1", TX_A)];
+    assert_eq!(*era, Era::Babbage);
+
+    let output = MultiEraOutput::decode(*era, cbor).unwrap();
+    assert_eq!(output.lovelace_amount(), 2000000);
+    assert_eq!(output.address().unwrap().to_string(), ADDRESS);
+    assert_eq!(output.non_ada_assets().len(), 2);
+
+    let (era, cbor) = &outputs[&format!("{}#3", TX_B)];
+    let output = MultiEraOutput::decode(*era, cbor).unwrap();
+    assert_eq!(output.lovelace_amount(), 7);
+}
+
+#[test]
+fn fetches_datums_by_hash() {
+    let url = spawn_stub(stub_routes());
+    let client = Client::new(&url, 1, Duration::from_secs(5)).unwrap();
+
+    let known = Hash::<32>::from_str(DATUM_HASH).unwrap();
+    assert_eq!(
+        client.fetch_datum(&known).unwrap(),
+        Some(vec![0xd8, 0x79, 0x80])
+    );
+
+    let unknown = Hash::<32>::from_str(TX_A).unwrap();
+    assert_eq!(client.fetch_datum(&unknown).unwrap(), None);
+}